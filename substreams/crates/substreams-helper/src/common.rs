@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use ethabi::ethereum_types::Address;
 
 use substreams::store::{
@@ -21,12 +23,24 @@ pub trait HasAddresser {
     fn has_address(&self, key: Address) -> bool;
 }
 
+impl<T: HasAddresser + ?Sized> HasAddresser for &T {
+    fn has_address(&self, key: Address) -> bool {
+        (**self).has_address(key)
+    }
+}
+
 impl HasAddresser for Vec<Address> {
     fn has_address(&self, key: Address) -> bool {
         self.contains(&key)
     }
 }
 
+impl HasAddresser for HashSet<Address> {
+    fn has_address(&self, key: Address) -> bool {
+        self.contains(&key)
+    }
+}
+
 impl HasAddresser for StoreGetString {
     fn has_address(&self, key: Address) -> bool {
         self.get_last(key.to_hex()).is_some()
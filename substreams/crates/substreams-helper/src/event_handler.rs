@@ -8,6 +8,8 @@ use substreams_ethereum::{
 
 use crate::common::HasAddresser;
 
+type LogHandler<'a> = Box<dyn FnMut(&eth::Log, &eth::TransactionTrace) + 'a>;
+
 /// Utility struct to easily filter events and assign them handlers.
 ///
 /// Usage:
@@ -21,6 +23,17 @@ use crate::common::HasAddresser;
 /// eh.handle_events(); // this will run all handlers
 /// ```
 ///
+/// Handlers can also filter by address individually, e.g. to handle factory events and the events
+/// of the pools it deployed (tracked in a store) in a single pass over the block. Pass a reference
+/// to share one `HasAddresser` between several handlers:
+/// ```ignore
+/// let mut eh = EventHandler::new(&block);
+/// eh.on_from::<PoolCreated, _>(factory_address, &mut on_pool_created);
+/// eh.on_from::<Swap, _>(&pools_store, &mut on_swap);
+/// eh.on_from::<Sync, _>(&pools_store, &mut on_sync);
+/// eh.handle_events();
+/// ```
+///
 /// You'll likely want to mutate some value from the handlers that is in the current scope.
 /// For that, make your handlers be closures, that close over the variable you want to mutate, and
 /// have the whole EventHandler block of code in its own scope (either by wrapping it in an aux
@@ -43,14 +56,14 @@ use crate::common::HasAddresser;
 /// ```
 pub struct EventHandler<'a> {
     block: &'a eth::Block,
-    #[allow(clippy::type_complexity)]
-    handlers: HashMap<&'static str, Box<dyn FnMut(&eth::Log, &eth::TransactionTrace) + 'a>>,
+    handlers: HashMap<&'static str, LogHandler<'a>>,
+    filtered_handlers: Vec<(Box<dyn HasAddresser + 'a>, LogHandler<'a>)>,
     addresses: Option<Box<dyn HasAddresser + 'a>>,
 }
 
 impl<'a> EventHandler<'a> {
     pub fn new(block: &'a eth::Block) -> Self {
-        Self { block, handlers: HashMap::new(), addresses: None }
+        Self { block, handlers: HashMap::new(), filtered_handlers: Vec::new(), addresses: None }
     }
 
     /// Sets the HasAddresser as a filter for which events to handle.
@@ -79,18 +92,41 @@ impl<'a> EventHandler<'a> {
         );
     }
 
+    /// Registers a handler to be run on a given event, only for events emitted by an address
+    /// found in the given `HasAddresser`.
+    ///
+    /// Unlike `filter_by_address`, the filter applies to this handler only, so events emitted by
+    /// different sets of contracts can be handled in a single pass over the block. Handlers
+    /// registered this way are not keyed by event name: several handlers may be registered for
+    /// the same event, e.g. with different address sets. If a filter was also set with
+    /// `filter_by_address`, events must pass both.
+    pub fn on_from<E: Event, F>(&mut self, addresser: impl HasAddresser + 'a, mut handler: F)
+    where
+        F: FnMut(E, &eth::TransactionTrace, &eth::Log) + 'a,
+    {
+        self.filtered_handlers.push((
+            Box::new(addresser),
+            Box::new(move |log: &eth::Log, tx: &eth::TransactionTrace| {
+                if let Some(event) = E::match_and_decode(log) {
+                    handler(event, tx, log);
+                }
+            }),
+        ));
+    }
+
     /// Will run all registered handlers for all events present on the block that match the given
     /// filters. You'll likely want to run this just once.
     pub fn handle_events(&mut self) {
         // Here we don't need to filter out failed transactions because logs only exist for
         // successful ones.
         for log in self.block.logs() {
+            let address = Address::from_slice(log.log.address.as_slice());
             if self.addresses.is_some() &&
                 !&self
                     .addresses
                     .as_ref()
                     .unwrap()
-                    .has_address(Address::from_slice(log.log.address.as_slice()))
+                    .has_address(address)
             {
                 continue;
             }
@@ -98,6 +134,93 @@ impl<'a> EventHandler<'a> {
             for handler in self.handlers.values_mut() {
                 handler(log.log, log.receipt.transaction);
             }
+
+            for (addresser, handler) in self.filtered_handlers.iter_mut() {
+                if addresser.has_address(address) {
+                    handler(log.log, log.receipt.transaction);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use substreams_ethereum::pb::eth::v2::{TransactionReceipt, TransactionTraceStatus};
+
+    /// Minimal event carrying a single byte, matched on its first topic.
+    struct Ping(u8);
+
+    impl Event for Ping {
+        const NAME: &'static str = "Ping";
+
+        fn match_log(log: &eth::Log) -> bool {
+            log.topics.first() == Some(&b"ping".to_vec())
+        }
+
+        fn decode(log: &eth::Log) -> Result<Self, String> {
+            Ok(Ping(log.data[0]))
         }
     }
+
+    fn address(byte: u8) -> Address {
+        Address::repeat_byte(byte)
+    }
+
+    fn block(logs: Vec<(u8, &str, u8)>) -> eth::Block {
+        let logs = logs
+            .into_iter()
+            .map(|(emitter, topic, value)| eth::Log {
+                address: address(emitter).as_bytes().to_vec(),
+                topics: vec![topic.as_bytes().to_vec()],
+                data: vec![value],
+                ..Default::default()
+            })
+            .collect();
+        eth::Block {
+            transaction_traces: vec![eth::TransactionTrace {
+                status: TransactionTraceStatus::Succeeded.into(),
+                receipt: Some(TransactionReceipt { logs, ..Default::default() }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_on_from_same_event_different_addresses() {
+        let block = block(vec![(1, "ping", 1), (2, "ping", 2), (3, "ping", 3), (1, "pong", 4)]);
+        let pools = HashSet::from([address(1), address(2)]);
+        let mut from_factory = vec![];
+        let mut from_pools = vec![];
+        {
+            let mut eh = EventHandler::new(&block);
+            eh.on_from::<Ping, _>(address(1), |ping, _, _| from_factory.push(ping.0));
+            eh.on_from::<Ping, _>(&pools, |ping, _, _| from_pools.push(ping.0));
+            eh.handle_events();
+        }
+
+        assert_eq!(from_factory, vec![1]);
+        assert_eq!(from_pools, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_on_from_with_global_filter() {
+        let block = block(vec![(1, "ping", 1), (2, "ping", 2), (3, "ping", 3)]);
+        let mut all = vec![];
+        let mut filtered = vec![];
+        {
+            let mut eh = EventHandler::new(&block);
+            eh.filter_by_address(vec![address(1), address(2)]);
+            eh.on::<Ping, _>(|ping, _, _| all.push(ping.0));
+            eh.on_from::<Ping, _>(vec![address(2), address(3)], |ping, _, _| filtered.push(ping.0));
+            eh.handle_events();
+        }
+
+        // Events must pass both the global and the handler's filter.
+        assert_eq!(all, vec![1, 2]);
+        assert_eq!(filtered, vec![2]);
+    }
 }
@@ -2,4 +2,6 @@ pub mod common;
 
 pub mod event_handler;
 pub mod hex;
+pub mod params;
 pub mod storage_change;
+pub mod storage_slot;
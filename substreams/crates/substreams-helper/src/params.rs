@@ -0,0 +1,152 @@
+use ethabi::ethereum_types::Address;
+use substreams::errors::Error;
+use tiny_keccak::{Hasher, Keccak};
+
+/// Parses an address passed through substreams module params.
+///
+/// Accepts lowercase, uppercase and EIP-55 checksummed hex strings, with or without the `0x`
+/// prefix. Surrounding whitespace is ignored. If the value mixes upper and lower case letters it
+/// is treated as checksummed and the checksum must be valid.
+///
+/// `field` is the name of the param and is only used for error messages, e.g.:
+/// ```ignore
+/// let factory = parse_address("factory_address", &params.factory_address)?;
+/// ```
+///
+/// ## Errors
+/// Returns an error naming the field if the value is not 20 bytes of hex or if its checksum is
+/// invalid.
+pub fn parse_address(field: &str, value: &str) -> Result<Address, Error> {
+    let trimmed = value.trim();
+    let hex_str = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+
+    if hex_str.len() != 40 {
+        return Err(Error::msg(format!(
+            "Invalid address for param `{field}`: expected 40 hex characters, got {} ('{value}')",
+            hex_str.len()
+        )));
+    }
+    let bytes = hex::decode(hex_str)
+        .map_err(|e| Error::msg(format!("Invalid address for param `{field}`: {e} ('{value}')")))?;
+
+    let has_lower = hex_str
+        .chars()
+        .any(|c| c.is_ascii_lowercase());
+    let has_upper = hex_str
+        .chars()
+        .any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper && to_checksum(&bytes) != hex_str {
+        return Err(Error::msg(format!(
+            "Invalid address for param `{field}`: bad EIP-55 checksum ('{value}')"
+        )));
+    }
+
+    Ok(Address::from_slice(&bytes))
+}
+
+/// Parses a comma separated list of addresses passed through substreams module params.
+///
+/// Each entry is parsed with `parse_address`. Empty entries are ignored, so an empty value yields
+/// an empty list, and duplicates are dropped while keeping the first occurrence's position, e.g.:
+/// ```ignore
+/// let pools = parse_address_list("pools", &params.pools)?;
+/// ```
+///
+/// ## Errors
+/// Returns an error naming the field and the position of the first invalid entry in the list,
+/// counting empty entries.
+pub fn parse_address_list(field: &str, value: &str) -> Result<Vec<Address>, Error> {
+    let mut addresses: Vec<Address> = Vec::new();
+    for (i, entry) in value
+        .split(',')
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, entry)| !entry.is_empty())
+    {
+        let address = parse_address(&format!("{field}[{i}]"), entry)?;
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+    Ok(addresses)
+}
+
+/// EIP-55 checksummed hex representation of an address, without `0x` prefix.
+fn to_checksum(address: &[u8]) -> String {
+    let lower = hex::encode(address);
+    let mut hash = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(lower.as_bytes());
+    hasher.finalize(&mut hash);
+
+    lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if c.is_ascii_alphabetic() && nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn test_parse_address_formats() {
+        let expected =
+            Address::from_slice(&hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap());
+        for value in [
+            CHECKSUMMED,
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+            "5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED",
+            " 5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\n",
+        ] {
+            assert_eq!(parse_address("factory", value).unwrap(), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn test_parse_address_errors() {
+        let err = parse_address("factory", "0x1234").unwrap_err();
+        assert!(err.to_string().contains("`factory`"));
+
+        let err = parse_address("vault", "0xzzaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap_err();
+        assert!(err.to_string().contains("`vault`"));
+
+        // Flip the case of one letter to break the checksum.
+        let bad_checksum = CHECKSUMMED.replace("aA", "AA");
+        let err = parse_address("factory", &bad_checksum).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+    }
+
+    #[test]
+    fn test_parse_address_list() {
+        let first = parse_address("pool", CHECKSUMMED).unwrap();
+        let second = Address::repeat_byte(0x11);
+        let value =
+            format!(" {CHECKSUMMED}, 0x{},,{} ", "11".repeat(20), CHECKSUMMED.to_lowercase());
+
+        assert_eq!(parse_address_list("pools", &value).unwrap(), vec![first, second]);
+        assert!(parse_address_list("pools", "")
+            .unwrap()
+            .is_empty());
+
+        let err = parse_address_list("pools", &format!("{CHECKSUMMED},0x1234")).unwrap_err();
+        assert!(err.to_string().contains("`pools[1]`"));
+
+        // Positions count skipped empty entries.
+        let err = parse_address_list("pools", &format!("{CHECKSUMMED},,0x1234")).unwrap_err();
+        assert!(err.to_string().contains("`pools[2]`"));
+    }
+}
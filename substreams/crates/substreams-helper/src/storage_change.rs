@@ -1,5 +1,9 @@
+use ethabi::ethereum_types::Address;
+use hex_literal::hex;
 use substreams_ethereum::pb::eth::v2::StorageChange;
 
+use crate::storage_slot::to_word;
+
 pub trait StorageChangesFilter {
     fn filter_by_address(&self, contract_addr: &[u8; 20]) -> Vec<&StorageChange>;
 }
@@ -11,3 +15,135 @@ impl StorageChangesFilter for Vec<StorageChange> {
             .collect()
     }
 }
+
+/// `bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)`
+pub const EIP1967_IMPLEMENTATION_SLOT: [u8; 32] =
+    hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc");
+/// `bytes32(uint256(keccak256("eip1967.proxy.admin")) - 1)`
+pub const EIP1967_ADMIN_SLOT: [u8; 32] =
+    hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103");
+/// `bytes32(uint256(keccak256("eip1967.proxy.beacon")) - 1)`
+pub const EIP1967_BEACON_SLOT: [u8; 32] =
+    hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50");
+
+/// The proxy slots standardised by EIP-1967.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Eip1967Slot {
+    Implementation,
+    Admin,
+    Beacon,
+}
+
+impl Eip1967Slot {
+    /// Recognizes an EIP-1967 slot from a storage key.
+    pub fn from_key(key: &[u8]) -> Option<Self> {
+        match key {
+            k if k == EIP1967_IMPLEMENTATION_SLOT => Some(Self::Implementation),
+            k if k == EIP1967_ADMIN_SLOT => Some(Self::Admin),
+            k if k == EIP1967_BEACON_SLOT => Some(Self::Beacon),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> [u8; 32] {
+        match self {
+            Self::Implementation => EIP1967_IMPLEMENTATION_SLOT,
+            Self::Admin => EIP1967_ADMIN_SLOT,
+            Self::Beacon => EIP1967_BEACON_SLOT,
+        }
+    }
+}
+
+/// A decoded change of an EIP-1967 proxy slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eip1967Change {
+    /// The proxy contract whose slot changed.
+    pub proxy: Address,
+    pub slot: Eip1967Slot,
+    pub old_address: Address,
+    pub new_address: Address,
+    pub ordinal: u64,
+}
+
+/// Decodes a storage change of an EIP-1967 slot, e.g. a proxy upgrade.
+///
+/// Returns `None` if the change does not touch one of the EIP-1967 slots. The stored addresses
+/// are read from the lower 20 bytes of the slot values.
+pub fn decode_eip1967_change(change: &StorageChange) -> Option<Eip1967Change> {
+    let slot = Eip1967Slot::from_key(&change.key)?;
+    if change.address.len() != 20 || change.old_value.len() > 32 || change.new_value.len() > 32 {
+        return None;
+    }
+    Some(Eip1967Change {
+        proxy: Address::from_slice(&change.address),
+        slot,
+        old_address: Address::from_slice(&to_word(&change.old_value)[12..]),
+        new_address: Address::from_slice(&to_word(&change.new_value)[12..]),
+        ordinal: change.ordinal,
+    })
+}
+
+/// Decodes all EIP-1967 slot changes, keeping only those for which `proxy_predicate` returns
+/// true for the changed contract's address.
+pub fn eip1967_changes<'a, F: Fn(&[u8]) -> bool + 'a>(
+    changes: impl IntoIterator<Item = &'a StorageChange> + 'a,
+    proxy_predicate: F,
+) -> impl Iterator<Item = Eip1967Change> + 'a {
+    changes
+        .into_iter()
+        .filter(move |change| proxy_predicate(&change.address))
+        .filter_map(decode_eip1967_change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethabi::ethereum_types::U256;
+    use tiny_keccak::{Hasher, Keccak};
+
+    fn eip1967_slot(label: &str) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        let mut hasher = Keccak::v256();
+        hasher.update(label.as_bytes());
+        hasher.finalize(&mut hash);
+        let mut slot = [0u8; 32];
+        (U256::from_big_endian(&hash) - U256::one()).to_big_endian(&mut slot);
+        slot
+    }
+
+    #[test]
+    fn test_slot_constants() {
+        assert_eq!(eip1967_slot("eip1967.proxy.implementation"), EIP1967_IMPLEMENTATION_SLOT);
+        assert_eq!(eip1967_slot("eip1967.proxy.admin"), EIP1967_ADMIN_SLOT);
+        assert_eq!(eip1967_slot("eip1967.proxy.beacon"), EIP1967_BEACON_SLOT);
+    }
+
+    #[test]
+    fn test_decode_eip1967_change() {
+        let change = StorageChange {
+            address: vec![1; 20],
+            key: EIP1967_IMPLEMENTATION_SLOT.to_vec(),
+            old_value: to_word(&[2; 20]).to_vec(),
+            new_value: vec![3; 20],
+            ordinal: 7,
+        };
+
+        assert_eq!(
+            decode_eip1967_change(&change),
+            Some(Eip1967Change {
+                proxy: Address::repeat_byte(1),
+                slot: Eip1967Slot::Implementation,
+                old_address: Address::repeat_byte(2),
+                new_address: Address::repeat_byte(3),
+                ordinal: 7,
+            })
+        );
+
+        let other = StorageChange { key: vec![0; 32], ..change.clone() };
+        assert_eq!(decode_eip1967_change(&other), None);
+
+        let changes = vec![change, other];
+        assert_eq!(eip1967_changes(&changes, |addr| addr == [1; 20]).count(), 1);
+        assert_eq!(eip1967_changes(&changes, |addr| addr == [9; 20]).count(), 0);
+    }
+}
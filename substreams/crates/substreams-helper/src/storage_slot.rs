@@ -0,0 +1,214 @@
+//! Helpers to compute Solidity storage slots.
+//!
+//! Follows the Solidity storage layout: the value for `key` in a mapping stored at `slot` lives
+//! at `keccak256(key ++ slot)`, and the elements of a dynamic array stored at `slot` start at
+//! `keccak256(slot)`. Keys and slots are 32 byte big-endian words.
+use ethabi::ethereum_types::U256;
+use thiserror::Error;
+use tiny_keccak::{Hasher, Keccak};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PackedFieldError {
+    #[error("slot value is {0} bytes, expected at most 32")]
+    ValueTooLong(usize),
+    #[error("field of {width} bytes at offset {offset} does not fit in a 32 byte slot")]
+    OutOfBounds { offset: usize, width: usize },
+    #[error("field width must be at least 1 byte")]
+    ZeroWidth,
+}
+
+/// Left pads a big-endian value to a 32 byte word.
+///
+/// ## Panics
+/// If the input is longer than 32 bytes.
+pub fn to_word(value: &[u8]) -> [u8; 32] {
+    if value.len() > 32 {
+        panic!("cannot convert {} bytes to a 32 byte word", value.len());
+    }
+    let mut word = [0u8; 32];
+    word[32 - value.len()..].copy_from_slice(value);
+    word
+}
+
+/// Storage slot for a numeric slot index, e.g. the declaration position of a state variable.
+pub fn slot_from_index(index: u64) -> [u8; 32] {
+    to_word(&index.to_be_bytes())
+}
+
+/// Storage slot of `mapping[key]`, for a mapping stored at `slot`.
+///
+/// `key` must already be encoded as a 32 byte word; use `to_word` for addresses and integers.
+pub fn mapping_slot(key: &[u8; 32], slot: &[u8; 32]) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(key);
+    hasher.update(slot);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Storage slot of `mapping[address]`, for a mapping stored at `slot`.
+///
+/// ## Panics
+/// If the address is longer than 32 bytes.
+pub fn address_mapping_slot(address: &[u8], slot: &[u8; 32]) -> [u8; 32] {
+    mapping_slot(&to_word(address), slot)
+}
+
+/// Storage slot of `mapping[keys[0]][keys[1]]...`, for nested mappings stored at `slot`.
+///
+/// E.g. an ERC-20 allowance `allowance[owner][spender]` lives at
+/// `nested_mapping_slot(&[to_word(owner), to_word(spender)], &slot)`.
+pub fn nested_mapping_slot(keys: &[[u8; 32]], slot: &[u8; 32]) -> [u8; 32] {
+    keys.iter()
+        .fold(*slot, |current, key| mapping_slot(key, &current))
+}
+
+/// Storage slot of `array[index]`, for a dynamic array stored at `slot`.
+///
+/// `element_slots` is the number of slots each element occupies, e.g. 1 for `uint256[]` or the
+/// number of slots of a struct. Elements packed several per slot are not supported.
+pub fn array_element_slot(slot: &[u8; 32], index: u64, element_slots: u64) -> [u8; 32] {
+    let mut start = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(slot);
+    hasher.finalize(&mut start);
+
+    let offset = U256::from(index) * U256::from(element_slots);
+    let (element, _) = U256::from_big_endian(&start).overflowing_add(offset);
+    let mut output = [0u8; 32];
+    element.to_big_endian(&mut output);
+    output
+}
+
+/// Extracts a field packed into a storage slot value.
+///
+/// Solidity packs fields smaller than 32 bytes into a single slot starting at the lower-order
+/// end, so `byte_offset` is counted from the right (least significant byte) as in the compiler's
+/// storage layout output. E.g. a `uint112` declared after a `uint48` in the same slot has
+/// `byte_offset = 6` and `width = 14`.
+///
+/// Values shorter than 32 bytes are treated as left-padded with zeros. The field is returned
+/// left-padded to a 32 byte big-endian word.
+///
+/// ## Errors
+/// If the field does not fit within 32 bytes, has zero width or the value is longer than 32
+/// bytes.
+pub fn extract_packed_field(
+    value: &[u8],
+    byte_offset: usize,
+    width: usize,
+) -> Result<[u8; 32], PackedFieldError> {
+    if value.len() > 32 {
+        return Err(PackedFieldError::ValueTooLong(value.len()));
+    }
+    if width == 0 {
+        return Err(PackedFieldError::ZeroWidth);
+    }
+    if width > 32 || byte_offset > 32 - width {
+        return Err(PackedFieldError::OutOfBounds { offset: byte_offset, width });
+    }
+
+    let word = to_word(value);
+    let end = 32 - byte_offset;
+    let mut field = [0u8; 32];
+    field[32 - width..].copy_from_slice(&word[end - width..end]);
+    Ok(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_to_word() {
+        assert_eq!(
+            to_word(&hex!("dd62ed3e")),
+            hex!("00000000000000000000000000000000000000000000000000000000dd62ed3e")
+        );
+        assert_eq!(slot_from_index(1), to_word(&[1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_to_word_too_long() {
+        to_word(&[1u8; 33]);
+    }
+
+    #[test]
+    fn test_mapping_slot() {
+        // keccak256 of 64 zero bytes
+        assert_eq!(
+            mapping_slot(&[0u8; 32], &[0u8; 32]),
+            hex!("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+        );
+        assert_eq!(
+            address_mapping_slot(&[0u8; 20], &[0u8; 32]),
+            mapping_slot(&[0u8; 32], &[0u8; 32])
+        );
+    }
+
+    #[test]
+    fn test_nested_mapping_slot() {
+        let owner = to_word(&[1u8; 20]);
+        let spender = to_word(&[2u8; 20]);
+        let slot = slot_from_index(3);
+
+        assert_eq!(
+            nested_mapping_slot(&[owner, spender], &slot),
+            mapping_slot(&spender, &mapping_slot(&owner, &slot))
+        );
+        assert_eq!(nested_mapping_slot(&[], &slot), slot);
+    }
+
+    #[test]
+    fn test_array_element_slot() {
+        // keccak256(uint256(1))
+        let start = hex!("b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6");
+        let slot = slot_from_index(1);
+
+        assert_eq!(array_element_slot(&slot, 0, 1), start);
+        assert_eq!(
+            array_element_slot(&slot, 1, 2),
+            hex!("b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf8")
+        );
+    }
+
+    #[test]
+    fn test_extract_packed_field() {
+        // | 6 bytes padding | uint112 0x1111..11 | uint48 0x2222..22 | uint48 0x3333..33 |
+        // The fields sit at byte offsets 12, 6 and 0 from the right.
+        let value = hex!("0000000000001111111111111111111111111111222222222222333333333333");
+
+        assert_eq!(extract_packed_field(&value, 6, 6).unwrap(), to_word(&hex!("222222222222")));
+        assert_eq!(
+            extract_packed_field(&value, 12, 14).unwrap(),
+            to_word(&hex!("1111111111111111111111111111"))
+        );
+        assert_eq!(extract_packed_field(&value, 0, 32).unwrap(), value);
+        assert_eq!(extract_packed_field(&value, 26, 6).unwrap(), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_extract_packed_field_short_values() {
+        assert_eq!(extract_packed_field(&[], 12, 14).unwrap(), [0u8; 32]);
+        assert_eq!(extract_packed_field(&[0xab, 0xcd], 0, 1).unwrap(), to_word(&[0xcd]));
+        assert_eq!(extract_packed_field(&[0xab, 0xcd], 1, 1).unwrap(), to_word(&[0xab]));
+        assert_eq!(extract_packed_field(&[0xab, 0xcd], 2, 1).unwrap(), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_extract_packed_field_bounds() {
+        assert_eq!(
+            extract_packed_field(&[0u8; 32], 20, 14),
+            Err(PackedFieldError::OutOfBounds { offset: 20, width: 14 })
+        );
+        assert_eq!(
+            extract_packed_field(&[0u8; 32], usize::MAX, 2),
+            Err(PackedFieldError::OutOfBounds { offset: usize::MAX, width: 2 })
+        );
+        assert_eq!(extract_packed_field(&[0u8; 32], 0, 0), Err(PackedFieldError::ZeroWidth));
+        assert_eq!(extract_packed_field(&[0u8; 33], 0, 1), Err(PackedFieldError::ValueTooLong(33)));
+    }
+}
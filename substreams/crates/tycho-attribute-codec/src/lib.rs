@@ -0,0 +1,516 @@
+//! Codec for component attribute values.
+//!
+//! Implements the encodings `tycho-substreams` uses for attribute values, without depending on
+//! substreams, so attributes can be decoded by consumers (e.g. solvers or embedded simulations)
+//! that only see the raw bytes:
+//! - signed integers are big-endian two's complement bytes, as produced by
+//!   `BigInt::to_signed_bytes_be`,
+//! - unsigned integers, e.g. absolute balances, are big-endian magnitudes, as produced by
+//!   `BigInt::to_bytes_be().1`,
+//! - addresses are their raw bytes,
+//! - booleans (e.g. the `paused` attribute) are a single `0` or `1` byte,
+//! - lists are json arrays of 0x prefixed hex strings, as produced by `json_serialize_address_list`
+//!   and `json_serialize_bigint_list`.
+//!
+//! Integers are decoded into 256 bit values, which covers every `uint256` and `int256` an EVM
+//! contract can hold.
+//!
+//! The crate is `no_std` and only requires `alloc`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The integer does not fit in 256 bits.
+    Overflow { len: usize },
+    /// The value has an unexpected length, e.g. an address that is not 20 bytes.
+    InvalidLength { expected: usize, got: usize },
+    /// A boolean value other than `[0]` or `[1]`.
+    InvalidBool,
+    /// The value is not a json array of 0x prefixed hex strings.
+    InvalidList { position: usize },
+    /// A list entry is not valid hex.
+    InvalidHex { index: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Overflow { len } => {
+                write!(f, "integer of {len} bytes does not fit in 256 bits")
+            }
+            DecodeError::InvalidLength { expected, got } => {
+                write!(f, "expected {expected} bytes, got {got}")
+            }
+            DecodeError::InvalidBool => write!(f, "boolean must be encoded as [0] or [1]"),
+            DecodeError::InvalidList { position } => {
+                write!(f, "invalid json hex list at byte {position}")
+            }
+            DecodeError::InvalidHex { index } => write!(f, "invalid hex string at index {index}"),
+        }
+    }
+}
+
+/// Length of an address in bytes.
+pub const ADDRESS_LENGTH: usize = 20;
+
+/// A signed integer with a magnitude of up to 256 bits.
+///
+/// Stored as a sign and a big-endian magnitude so that both `uint256` and `int256` values fit.
+/// Zero is never negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Int256 {
+    negative: bool,
+    magnitude: [u8; 32],
+}
+
+impl Int256 {
+    pub const ZERO: Int256 = Int256 { negative: false, magnitude: [0; 32] };
+
+    /// Creates an integer from its sign and big-endian magnitude.
+    pub fn new(negative: bool, magnitude: [u8; 32]) -> Self {
+        Self { negative: negative && magnitude != [0; 32], magnitude }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The absolute value as a big-endian 32 byte word.
+    pub fn magnitude(&self) -> [u8; 32] {
+        self.magnitude
+    }
+
+    /// Converts to an `i128`, or `None` if the value does not fit.
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.magnitude[..16] != [0; 16] {
+            return None;
+        }
+        let mut low = [0u8; 16];
+        low.copy_from_slice(&self.magnitude[16..]);
+        let magnitude = u128::from_be_bytes(low);
+        if !self.negative {
+            i128::try_from(magnitude).ok()
+        } else if magnitude <= i128::MIN.unsigned_abs() {
+            // Wrapping handles i128::MIN, whose magnitude does not fit an i128.
+            Some((magnitude as i128).wrapping_neg())
+        } else {
+            None
+        }
+    }
+}
+
+impl From<i128> for Int256 {
+    fn from(value: i128) -> Self {
+        let mut magnitude = [0u8; 32];
+        magnitude[16..].copy_from_slice(&value.unsigned_abs().to_be_bytes());
+        Self::new(value < 0, magnitude)
+    }
+}
+
+/// Encodes a signed integer as minimal big-endian two's complement bytes.
+///
+/// Matches `BigInt::to_signed_bytes_be`: zero is encoded as `[0]` and positive values whose most
+/// significant bit is set get a leading zero byte.
+pub fn encode_signed(value: &Int256) -> Vec<u8> {
+    // One extra byte so that the sign bit of any 256 bit magnitude fits.
+    let mut bytes = [0u8; 33];
+    bytes[1..].copy_from_slice(&value.magnitude);
+    if value.negative {
+        negate(&mut bytes);
+    }
+    let padding = if value.negative { 0xff } else { 0x00 };
+    // Drop redundant sign bytes, keeping one if the next byte's sign bit would change the sign.
+    let mut start = 0;
+    while start < bytes.len() - 1 &&
+        bytes[start] == padding &&
+        (bytes[start + 1] & 0x80) == (padding & 0x80)
+    {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+/// Decodes big-endian two's complement bytes into a signed integer.
+///
+/// An empty value decodes to zero.
+///
+/// ## Errors
+/// If the magnitude does not fit in 256 bits.
+pub fn decode_signed(value: &[u8]) -> Result<Int256, DecodeError> {
+    let negative = value
+        .first()
+        .is_some_and(|b| b & 0x80 != 0);
+    if !negative {
+        return decode_unsigned(value).map(|magnitude| Int256::new(false, magnitude));
+    }
+
+    let mut magnitude = value.to_vec();
+    negate(&mut magnitude);
+    let significant = strip_leading(&magnitude, 0x00);
+    if significant.len() > 32 {
+        return Err(DecodeError::Overflow { len: value.len() });
+    }
+    let mut word = [0u8; 32];
+    word[32 - significant.len()..].copy_from_slice(significant);
+    Ok(Int256::new(true, word))
+}
+
+/// Encodes an unsigned big-endian word as a minimal magnitude.
+///
+/// Matches `BigInt::to_bytes_be().1`: leading zeros are dropped and zero is encoded as `[0]`.
+pub fn encode_unsigned(value: &[u8; 32]) -> Vec<u8> {
+    let significant = strip_leading(value, 0x00);
+    if significant.is_empty() {
+        alloc::vec![0]
+    } else {
+        significant.to_vec()
+    }
+}
+
+/// Decodes a big-endian magnitude, e.g. an absolute balance, into a 32 byte big-endian word.
+///
+/// The value is not interpreted as two's complement, so `[0xc8]` decodes to 200. An empty value
+/// decodes to zero.
+///
+/// ## Errors
+/// If the value does not fit in 256 bits.
+pub fn decode_unsigned(value: &[u8]) -> Result<[u8; 32], DecodeError> {
+    let significant = strip_leading(value, 0x00);
+    if significant.len() > 32 {
+        return Err(DecodeError::Overflow { len: value.len() });
+    }
+    let mut word = [0u8; 32];
+    word[32 - significant.len()..].copy_from_slice(significant);
+    Ok(word)
+}
+
+/// Decodes an address attribute.
+///
+/// ## Errors
+/// If the value is not exactly 20 bytes long.
+pub fn decode_address(value: &[u8]) -> Result<[u8; ADDRESS_LENGTH], DecodeError> {
+    value
+        .try_into()
+        .map_err(|_| DecodeError::InvalidLength { expected: ADDRESS_LENGTH, got: value.len() })
+}
+
+/// Encodes a boolean attribute as a single byte.
+pub fn encode_bool(value: bool) -> Vec<u8> {
+    alloc::vec![u8::from(value)]
+}
+
+/// Decodes a boolean attribute.
+///
+/// ## Errors
+/// If the value is anything but `[0]` or `[1]`.
+pub fn decode_bool(value: &[u8]) -> Result<bool, DecodeError> {
+    match value {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(DecodeError::InvalidBool),
+    }
+}
+
+/// Encodes a list of byte strings as a json array of 0x prefixed hex strings.
+///
+/// The output is byte-for-byte identical to `json_serialize_address_list` and, for values
+/// encoded with `encode_signed`, to `json_serialize_bigint_list`.
+pub fn encode_hex_list<T: AsRef<[u8]>>(values: &[T]) -> Vec<u8> {
+    let mut out = String::from("[");
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("\"0x");
+        for byte in value.as_ref() {
+            out.push(hex_char(byte >> 4));
+            out.push(hex_char(byte & 0x0f));
+        }
+        out.push('"');
+    }
+    out.push(']');
+    out.into_bytes()
+}
+
+/// Decodes a json array of 0x prefixed hex strings into the raw bytes of each entry.
+///
+/// Whitespace between tokens is accepted. Entries with an odd number of hex digits are treated
+/// as left-padded with a zero.
+///
+/// ## Errors
+/// If the value is not a json array of strings or an entry is not 0x prefixed hex.
+pub fn decode_hex_list(value: &[u8]) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let mut pos = skip_whitespace(value, 0);
+    if value.get(pos) != Some(&b'[') {
+        return Err(DecodeError::InvalidList { position: pos });
+    }
+    pos = skip_whitespace(value, pos + 1);
+
+    let mut entries = Vec::new();
+    if value.get(pos) == Some(&b']') {
+        return finish_list(value, pos, entries);
+    }
+    loop {
+        if value.get(pos) != Some(&b'"') {
+            return Err(DecodeError::InvalidList { position: pos });
+        }
+        let start = pos + 1;
+        let len = value[start..]
+            .iter()
+            .position(|b| *b == b'"')
+            .ok_or(DecodeError::InvalidList { position: pos })?;
+        entries.push(decode_hex(&value[start..start + len], entries.len())?);
+
+        pos = skip_whitespace(value, start + len + 1);
+        match value.get(pos) {
+            Some(b',') => pos = skip_whitespace(value, pos + 1),
+            Some(b']') => return finish_list(value, pos, entries),
+            _ => return Err(DecodeError::InvalidList { position: pos }),
+        }
+    }
+}
+
+/// Decodes a json list of signed integers, e.g. a `json_serialize_bigint_list` attribute.
+///
+/// ## Errors
+/// See `decode_hex_list` and `decode_signed`.
+pub fn decode_signed_list(value: &[u8]) -> Result<Vec<Int256>, DecodeError> {
+    decode_hex_list(value)?
+        .iter()
+        .map(|entry| decode_signed(entry))
+        .collect()
+}
+
+/// Decodes a json list of addresses, e.g. a `json_serialize_address_list` attribute.
+///
+/// ## Errors
+/// See `decode_hex_list` and `decode_address`.
+pub fn decode_address_list(value: &[u8]) -> Result<Vec<[u8; ADDRESS_LENGTH]>, DecodeError> {
+    decode_hex_list(value)?
+        .iter()
+        .map(|entry| decode_address(entry))
+        .collect()
+}
+
+/// Negates big-endian two's complement bytes in place.
+fn negate(bytes: &mut [u8]) {
+    let mut carry = true;
+    for byte in bytes.iter_mut().rev() {
+        let (sum, overflow) = (!*byte).overflowing_add(u8::from(carry));
+        *byte = sum;
+        carry = overflow;
+    }
+}
+
+fn strip_leading(value: &[u8], padding: u8) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|b| *b != padding)
+        .unwrap_or(value.len());
+    &value[start..]
+}
+
+fn skip_whitespace(value: &[u8], mut pos: usize) -> usize {
+    while value
+        .get(pos)
+        .is_some_and(|b| b.is_ascii_whitespace())
+    {
+        pos += 1;
+    }
+    pos
+}
+
+fn finish_list(
+    value: &[u8],
+    closing: usize,
+    entries: Vec<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let end = skip_whitespace(value, closing + 1);
+    if end != value.len() {
+        return Err(DecodeError::InvalidList { position: end });
+    }
+    Ok(entries)
+}
+
+fn decode_hex(entry: &[u8], index: usize) -> Result<Vec<u8>, DecodeError> {
+    let digits = entry
+        .strip_prefix(b"0x")
+        .ok_or(DecodeError::InvalidHex { index })?;
+    let nibble = |c: u8| match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(DecodeError::InvalidHex { index }),
+    };
+
+    let mut out = Vec::with_capacity(digits.len().div_ceil(2));
+    let (head, rest) = digits.split_at(digits.len() % 2);
+    if let [c] = head {
+        out.push(nibble(*c)?);
+    }
+    for pair in rest.chunks(2) {
+        out.push((nibble(pair[0])? << 4) | nibble(pair[1])?);
+    }
+    Ok(out)
+}
+
+fn hex_char(nibble: u8) -> char {
+    char::from_digit(u32::from(nibble), 16).expect("nibble is always < 16")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn word(significant: &[u8]) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[32 - significant.len()..].copy_from_slice(significant);
+        word
+    }
+
+    #[test]
+    fn test_signed_roundtrip() {
+        for (value, encoded) in [
+            (0i128, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7f]),
+            (128, vec![0x00, 0x80]),
+            (-1, vec![0xff]),
+            (-128, vec![0x80]),
+            (-129, vec![0xff, 0x7f]),
+            (1_000_000, vec![0x0f, 0x42, 0x40]),
+        ] {
+            assert_eq!(encode_signed(&Int256::from(value)), encoded, "{value}");
+            assert_eq!(decode_signed(&encoded), Ok(Int256::from(value)), "{value}");
+        }
+        for value in [i128::MAX, i128::MIN] {
+            let decoded = decode_signed(&encode_signed(&Int256::from(value))).unwrap();
+            assert_eq!(decoded.to_i128(), Some(value));
+        }
+        assert_eq!(decode_signed(&[]), Ok(Int256::ZERO));
+        assert_eq!(decode_signed(&[0x00, 0x00, 0x01]), Ok(Int256::from(1)));
+    }
+
+    #[test]
+    fn test_signed_256_bit_values() {
+        // A uint160 with its top bit set, e.g. a `sqrt_price_x96`, takes 21 bytes.
+        let mut uint160 = vec![0x00];
+        uint160.extend([0xff; 20]);
+        let decoded = decode_signed(&uint160).unwrap();
+        assert!(!decoded.is_negative());
+        assert_eq!(decoded.magnitude(), word(&[0xff; 20]));
+        assert_eq!(decoded.to_i128(), None);
+        assert_eq!(encode_signed(&decoded), uint160);
+
+        // uint256::MAX and its negation need 33 bytes.
+        let mut max = vec![0x00];
+        max.extend([0xff; 32]);
+        assert_eq!(decode_signed(&max), Ok(Int256::new(false, [0xff; 32])));
+        assert_eq!(encode_signed(&Int256::new(false, [0xff; 32])), max);
+
+        let mut min = vec![0xff];
+        min.extend([0x00; 31]);
+        min.push(0x01);
+        assert_eq!(decode_signed(&min), Ok(Int256::new(true, [0xff; 32])));
+        assert_eq!(encode_signed(&Int256::new(true, [0xff; 32])), min);
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let mut too_large = vec![0x01];
+        too_large.extend([0x00; 32]);
+        assert_eq!(decode_signed(&too_large), Err(DecodeError::Overflow { len: 33 }));
+        assert_eq!(decode_unsigned(&too_large), Err(DecodeError::Overflow { len: 33 }));
+
+        // -2^256
+        let mut too_small = vec![0xff];
+        too_small.extend([0x00; 32]);
+        assert_eq!(decode_signed(&too_small), Err(DecodeError::Overflow { len: 33 }));
+    }
+
+    #[test]
+    fn test_unsigned() {
+        // Balances are magnitudes: 200 is [0xc8], not -56.
+        assert_eq!(decode_unsigned(&[0xc8]), Ok(word(&[0xc8])));
+        assert_eq!(encode_unsigned(&word(&[0xc8])), vec![0xc8]);
+        assert_eq!(encode_unsigned(&[0; 32]), vec![0x00]);
+        assert_eq!(decode_unsigned(&[]), Ok([0; 32]));
+        assert_eq!(decode_unsigned(&[0xff; 32]), Ok([0xff; 32]));
+
+        let mut padded = vec![0x00];
+        padded.extend([0xff; 32]);
+        assert_eq!(decode_unsigned(&padded), Ok([0xff; 32]));
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        assert_eq!(Int256::new(true, [0; 32]), Int256::ZERO);
+        assert_eq!(encode_signed(&Int256::new(true, [0; 32])), vec![0x00]);
+    }
+
+    #[test]
+    fn test_address_and_bool() {
+        assert_eq!(decode_address(&[0xab; 20]), Ok([0xab; 20]));
+        assert_eq!(
+            decode_address(&[0xab; 32]),
+            Err(DecodeError::InvalidLength { expected: 20, got: 32 })
+        );
+
+        assert_eq!(decode_bool(&encode_bool(true)), Ok(true));
+        assert_eq!(decode_bool(&encode_bool(false)), Ok(false));
+        assert_eq!(decode_bool(&[2]), Err(DecodeError::InvalidBool));
+    }
+
+    #[test]
+    fn test_hex_list_roundtrip() {
+        let addresses = [[0x11u8; 20], [0xabu8; 20]];
+        let encoded = encode_hex_list(&addresses);
+        assert_eq!(
+            encoded,
+            alloc::format!("[\"0x{}\",\"0x{}\"]", "11".repeat(20), "ab".repeat(20)).into_bytes()
+        );
+        assert_eq!(decode_address_list(&encoded), Ok(addresses.to_vec()));
+
+        let values = [0i128, -1, 1_000_000].map(Int256::from);
+        let encoded = encode_hex_list(
+            &values
+                .iter()
+                .map(encode_signed)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(encoded, b"[\"0x00\",\"0xff\",\"0x0f4240\"]".to_vec());
+        assert_eq!(decode_signed_list(&encoded), Ok(values.to_vec()));
+
+        assert_eq!(encode_hex_list::<Vec<u8>>(&[]), b"[]".to_vec());
+        assert_eq!(decode_hex_list(b" [ ] "), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_decode_hex_list_formats() {
+        assert_eq!(
+            decode_hex_list(b"[ \"0xABc\" , \"0x\" ]\n"),
+            Ok(vec![vec![0x0a, 0xbc], vec![]])
+        );
+    }
+
+    #[test]
+    fn test_decode_hex_list_errors() {
+        assert_eq!(decode_hex_list(b""), Err(DecodeError::InvalidList { position: 0 }));
+        assert_eq!(decode_hex_list(b"[\"0x01\""), Err(DecodeError::InvalidList { position: 7 }));
+        assert_eq!(decode_hex_list(b"[\"0x01\",]"), Err(DecodeError::InvalidList { position: 8 }));
+        assert_eq!(decode_hex_list(b"[\"0x01\"] x"), Err(DecodeError::InvalidList { position: 9 }));
+        assert_eq!(decode_hex_list(b"[\"0x01"), Err(DecodeError::InvalidList { position: 1 }));
+        assert_eq!(decode_hex_list(b"[\"0x0g\"]"), Err(DecodeError::InvalidHex { index: 0 }));
+        assert_eq!(decode_hex_list(b"[\"0x\",\"01\"]"), Err(DecodeError::InvalidHex { index: 1 }));
+        assert_eq!(
+            decode_address_list(b"[\"0x01\"]"),
+            Err(DecodeError::InvalidLength { expected: 20, got: 1 })
+        );
+    }
+}
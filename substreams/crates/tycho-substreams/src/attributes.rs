@@ -1,6 +1,10 @@
+use crate::models::{Attribute, ChangeType, EntityChanges};
 use std::fmt::Debug;
 use substreams::prelude::BigInt;
 
+/// Name of the attribute flagging a component as paused.
+pub const PAUSED_ATTRIBUTE: &str = "paused";
+
 /// Encodes a value to bytes using json.
 ///
 /// ## Panics
@@ -44,3 +48,50 @@ pub fn json_serialize_bigint_list(values: &[BigInt]) -> Vec<u8> {
             .collect::<Vec<_>>(),
     )
 }
+
+/// Builds the attribute change that pauses or unpauses a component.
+///
+/// Paused components carry a `paused` attribute of `[1]`, unpausing sets it to `[0]`. Use this
+/// for every pause trigger of a protocol so they all emit the same attribute.
+pub fn paused_attribute(paused: bool) -> Attribute {
+    Attribute {
+        name: PAUSED_ATTRIBUTE.to_string(),
+        value: vec![u8::from(paused)],
+        change: ChangeType::Update.into(),
+    }
+}
+
+/// Builds the entity change that pauses or unpauses the given component.
+///
+/// See `paused_attribute`.
+pub fn paused_entity_change(component_id: &str, paused: bool) -> EntityChanges {
+    EntityChanges {
+        component_id: component_id.to_string(),
+        attributes: vec![paused_attribute(paused)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paused_attribute_encoding() {
+        assert_eq!(
+            paused_attribute(true),
+            Attribute {
+                name: "paused".to_string(),
+                value: vec![1],
+                change: ChangeType::Update.into(),
+            }
+        );
+        assert_eq!(paused_attribute(false).value, vec![0]);
+        assert_eq!(
+            paused_entity_change("0x01", true),
+            EntityChanges {
+                component_id: "0x01".to_string(),
+                attributes: vec![paused_attribute(true)],
+            }
+        );
+    }
+}
@@ -27,7 +27,10 @@ use crate::{
     prelude::BalanceDelta,
 };
 use itertools::Itertools;
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 use substreams::{
     key,
     pb::substreams::StoreDeltas,
@@ -88,6 +91,81 @@ pub fn store_balance_changes(deltas: BlockBalanceDeltas, store: impl StoreAdd<Bi
         });
 }
 
+/// How `merge_block_balance_deltas` resolves deltas that different sources emit for the same
+/// transaction, ordinal, component and token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaConflict {
+    /// Keep the deltas of the first source, in the order the sources are given, and drop the
+    /// others. Use this when sources may observe the same event, e.g. a swap-derived and a
+    /// refresh-derived source both decoding the same log.
+    PreferFirst,
+    /// Sum the deltas of all sources. Only correct if the sources observe disjoint events that
+    /// happen to share an ordinal.
+    Sum,
+}
+
+/// Merges `BlockBalanceDeltas` from several sources into a single message.
+///
+/// Use this to combine balance deltas from different maps (e.g. swap events and vault events)
+/// before passing them to `store_balance_changes`, instead of running a store per source.
+///
+/// The merged deltas are sorted by transaction index, ordinal, component and token. Deltas for
+/// the same component and token at the same ordinal are combined into a single delta, which keeps
+/// ordinals strictly increasing per component and token as required by `store_balance_changes`.
+/// Deltas of a single source are summed; collisions between sources are resolved by `conflict`.
+///
+/// ## Panics
+/// If any delta is missing its transaction.
+pub fn merge_block_balance_deltas(
+    sources: impl IntoIterator<Item = BlockBalanceDeltas>,
+    conflict: DeltaConflict,
+) -> BlockBalanceDeltas {
+    let sorted = sources
+        .into_iter()
+        .enumerate()
+        .flat_map(|(source, deltas)| {
+            deltas
+                .balance_deltas
+                .into_iter()
+                .map(move |delta| (source, delta))
+        })
+        .map(|(source, delta)| {
+            let tx_index = delta
+                .tx
+                .as_ref()
+                .expect("Missing transaction on delta")
+                .index;
+            ((tx_index, delta.ord, delta.component_id.clone(), delta.token.clone()), source, delta)
+        })
+        // Stable sort: within a key, deltas stay ordered by source.
+        .sorted_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+    let mut balance_deltas: Vec<BalanceDelta> = Vec::new();
+    // Key and source of the last pushed delta.
+    let mut previous = None;
+    for (key, source, delta) in sorted {
+        let Some(kept_source) = previous
+            .as_ref()
+            .filter(|(previous_key, _)| *previous_key == key)
+            .map(|(_, kept_source)| *kept_source)
+        else {
+            balance_deltas.push(delta);
+            previous = Some((key, source));
+            continue;
+        };
+        if source != kept_source && conflict == DeltaConflict::PreferFirst {
+            continue;
+        }
+        let last = balance_deltas
+            .last_mut()
+            .expect("a delta was pushed for this key");
+        let sum =
+            BigInt::from_signed_bytes_be(&last.delta) + BigInt::from_signed_bytes_be(&delta.delta);
+        last.delta = sum.to_signed_bytes_be();
+    }
+    BlockBalanceDeltas { balance_deltas }
+}
+
 type TxAggregatedBalances =
     HashMap<Vec<u8>, (Transaction, HashMap<Vec<u8>, HashMap<Vec<u8>, BalanceChange>>)>;
 
@@ -245,6 +323,246 @@ pub fn extract_balance_deltas_from_tx<F: Fn(&[u8], &[u8]) -> bool>(
     balance_deltas
 }
 
+/// Tracks a component's ERC-20 balances through `Transfer` events.
+///
+/// The component's balance of a token is the sum of that token held by the tracked owner
+/// addresses, e.g. a pool contract and its escrow. Transfers between two owners of the same
+/// component cancel out and are ignored. Transfers of untracked tokens are ignored.
+///
+/// WETH `Deposit` and `Withdrawal` events are handled as well, since WETH does not emit a
+/// `Transfer` when an owner wraps or unwraps ether.
+///
+/// # Example
+///
+/// ```ignore
+/// let tracker = TransferBalanceTracker::new(&component.id, &[pool, escrow], &component.tokens);
+/// let deltas = block
+///     .transactions()
+///     .flat_map(|tx| tracker.extract_balance_deltas(tx))
+///     .collect::<Vec<_>>();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TransferBalanceTracker {
+    component_id: String,
+    owners: HashSet<Vec<u8>>,
+    tokens: HashSet<Vec<u8>>,
+}
+
+impl TransferBalanceTracker {
+    /// Creates a tracker for the given component.
+    ///
+    /// ## Arguments
+    /// * `component_id` - The id used for the emitted `BalanceDelta`s.
+    /// * `owners` - Addresses whose token holdings make up the component's balances.
+    /// * `tokens` - ERC-20 tokens to track.
+    pub fn new<O: AsRef<[u8]>, T: AsRef<[u8]>>(
+        component_id: &str,
+        owners: &[O],
+        tokens: &[T],
+    ) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            owners: owners
+                .iter()
+                .map(|o| o.as_ref().to_vec())
+                .collect(),
+            tokens: tokens
+                .iter()
+                .map(|t| t.as_ref().to_vec())
+                .collect(),
+        }
+    }
+
+    /// Extracts the component's balance deltas from a transaction's `Transfer`, and WETH
+    /// `Deposit` and `Withdrawal`, events.
+    ///
+    /// Emits at most one delta per log, using the log's ordinal, so the deltas can be passed to
+    /// `store_balance_changes` directly. Logs of reverted calls are skipped.
+    pub fn extract_balance_deltas(&self, tx: &TransactionTrace) -> Vec<BalanceDelta> {
+        tx.logs_with_calls()
+            .filter(|(log, _)| self.tokens.contains(&log.address))
+            .filter_map(|(log, _)| {
+                let delta = if let Some(transfer) =
+                    abi::erc20::events::Transfer::match_and_decode(log)
+                {
+                    let incoming = self.owners.contains(&transfer.to);
+                    let outgoing = self.owners.contains(&transfer.from);
+                    match (incoming, outgoing) {
+                        (true, false) => transfer.value,
+                        (false, true) => transfer.value.neg(),
+                        _ => return None,
+                    }
+                } else if let Some(deposit) = abi::weth::events::Deposit::match_and_decode(log) {
+                    if !self.owners.contains(&deposit.dst) {
+                        return None;
+                    }
+                    deposit.wad
+                } else if let Some(withdrawal) =
+                    abi::weth::events::Withdrawal::match_and_decode(log)
+                {
+                    if !self.owners.contains(&withdrawal.src) {
+                        return None;
+                    }
+                    withdrawal.wad.neg()
+                } else {
+                    return None;
+                };
+                Some(BalanceDelta {
+                    ord: log.ordinal,
+                    tx: Some(tx.into()),
+                    token: log.address.clone(),
+                    delta: delta.to_signed_bytes_be(),
+                    component_id: self.component_id.as_bytes().to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Describes a token balance that a contract keeps in one of its storage slots.
+///
+/// The balance may be packed with other fields; its position is given as a byte offset from the
+/// least significant end of the slot and a width, as in the Solidity storage layout. E.g. a
+/// `uint112` stored after a `uint48` has `byte_offset = 6` and `width = 14`.
+///
+/// # Example
+///
+/// ```ignore
+/// let specs = [
+///     SlotBalance::new(&vault, &CASH_SLOT, 6, 14, &asset, &component_id),
+///     SlotBalance::new(&pool, &RESERVES_SLOT, 0, 14, &token_0, &component_id),
+/// ];
+/// let balances = extract_balances_from_slots(tx, &specs);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotBalance {
+    contract: Vec<u8>,
+    slot: [u8; 32],
+    byte_offset: usize,
+    width: usize,
+    token: Vec<u8>,
+    component_id: String,
+}
+
+impl SlotBalance {
+    /// Creates a new slot balance specification.
+    ///
+    /// `slot` is the full 32 byte storage key, as found in storage changes. Use e.g.
+    /// `substreams_helper::storage_slot::slot_from_index` for slots given as an index.
+    ///
+    /// ## Panics
+    /// If the field does not fit into a 32 byte slot or has zero width.
+    pub fn new(
+        contract: &[u8],
+        slot: &[u8; 32],
+        byte_offset: usize,
+        width: usize,
+        token: &[u8],
+        component_id: &str,
+    ) -> Self {
+        if width == 0 || width > 32 || byte_offset > 32 - width {
+            panic!("Invalid slot balance field: offset {byte_offset}, width {width}");
+        }
+        Self {
+            contract: contract.to_vec(),
+            slot: *slot,
+            byte_offset,
+            width,
+            token: token.to_vec(),
+            component_id: component_id.to_string(),
+        }
+    }
+
+    pub fn contract(&self) -> &[u8] {
+        &self.contract
+    }
+
+    pub fn slot(&self) -> &[u8; 32] {
+        &self.slot
+    }
+
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+
+    pub fn component_id(&self) -> &str {
+        &self.component_id
+    }
+
+    /// Reads the balance from a slot value, values shorter than 32 bytes are left padded.
+    ///
+    /// ## Panics
+    /// If the value is longer than 32 bytes, which is not a valid storage value.
+    fn read(&self, value: &[u8]) -> BigInt {
+        if value.len() > 32 {
+            panic!("Invalid storage value of {} bytes for slot balance", value.len());
+        }
+        let mut word = [0u8; 32];
+        word[32 - value.len()..].copy_from_slice(value);
+        // `new` guarantees the field lies within the word.
+        let end = 32 - self.byte_offset;
+        BigInt::from_unsigned_bytes_be(&word[end - self.width..end])
+    }
+}
+
+/// Extracts absolute balances from storage changes of a transaction.
+///
+/// For each specification, reads the balance from the final value of its slot within the
+/// transaction, ignoring reverted calls. A balance change is only emitted if the balance differs
+/// from its value at the start of the transaction, so writes to other fields packed into the
+/// same slot do not produce changes.
+///
+/// The final value is the write with the highest ordinal across the whole call tree, regardless
+/// of the order of calls in the trace. This covers state settled at the end of an outer call,
+/// e.g. after deferred status checks, as well as intermediate writes that are later undone.
+///
+/// ## Returns
+/// The balance changes in the order of `specs`.
+///
+/// ## Panics
+/// If a matching storage value is longer than 32 bytes.
+pub fn extract_balances_from_slots(
+    tx: &TransactionTrace,
+    specs: &[SlotBalance],
+) -> Vec<BalanceChange> {
+    let storage_changes = tx
+        .calls
+        .iter()
+        .filter(|call| !call.state_reverted)
+        .flat_map(|call| call.storage_changes.iter())
+        .sorted_by_key(|change| change.ordinal)
+        .collect::<Vec<_>>();
+
+    specs
+        .iter()
+        .filter_map(|spec| {
+            let mut changes = storage_changes
+                .iter()
+                .filter(|change| change.address == spec.contract && change.key == spec.slot);
+            let first = changes.next()?;
+            let last = changes.last().unwrap_or(first);
+
+            let balance = spec.read(&last.new_value);
+            if spec.read(&first.old_value) == balance {
+                return None;
+            }
+            Some(BalanceChange {
+                token: spec.token.clone(),
+                balance: balance.to_bytes_be().1,
+                component_id: spec.component_id.as_bytes().to_vec(),
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -253,6 +571,7 @@ mod tests {
         pb::substreams::StoreDelta,
         prelude::{StoreGet, StoreNew},
     };
+    use substreams_ethereum::pb::eth::v2::{Call, Log, StorageChange};
 
     fn block_balance_deltas() -> BlockBalanceDeltas {
         let comp_id = "0x42c0ffee"
@@ -469,4 +788,377 @@ mod tests {
         let res = aggregate_balances_changes(store_deltas, balance_deltas);
         assert_eq!(res, exp);
     }
+
+    #[test]
+    fn test_store_and_aggregate_with_mock_deltas() {
+        let comp_id = "0x42c0ffee"
+            .to_string()
+            .as_bytes()
+            .to_vec();
+        let token_0 = hex::decode("bad999").unwrap();
+        let store = <MockStore as StoreNew>::new();
+
+        store_balance_changes(block_balance_deltas(), store.clone());
+        let res = aggregate_balances_changes(store.deltas(), block_balance_deltas());
+
+        let (_, balances) = &res[&vec![0, 1]];
+        assert_eq!(balances[&comp_id][&token_0].balance, BigInt::from(999).to_bytes_be().1);
+    }
+
+    fn transfer_log(token: &[u8], from: &[u8], to: &[u8], value: u64, ordinal: u64) -> Log {
+        let topic = |address: &[u8]| {
+            let mut word = vec![0u8; 12];
+            word.extend_from_slice(address);
+            word
+        };
+        let mut data = vec![0u8; 24];
+        data.extend_from_slice(&value.to_be_bytes());
+        Log {
+            address: token.to_vec(),
+            topics: vec![
+                hex::decode("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef")
+                    .unwrap(),
+                topic(from),
+                topic(to),
+            ],
+            data,
+            ordinal,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_transfer_balance_tracker() {
+        let token = [0xaa; 20];
+        let other_token = [0xbb; 20];
+        let pool = [1; 20];
+        let escrow = [2; 20];
+        let user = [3; 20];
+        let tx = TransactionTrace {
+            calls: vec![
+                Call {
+                    logs: vec![
+                        transfer_log(&token, &user, &pool, 100, 1),
+                        transfer_log(&token, &pool, &escrow, 60, 2),
+                        transfer_log(&other_token, &user, &pool, 5, 3),
+                        transfer_log(&token, &escrow, &user, 10, 4),
+                    ],
+                    ..Default::default()
+                },
+                Call {
+                    state_reverted: true,
+                    logs: vec![transfer_log(&token, &user, &pool, 1000, 5)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let tracker = TransferBalanceTracker::new("0xc0ffee", &[pool, escrow], &[token]);
+        let deltas = tracker.extract_balance_deltas(&tx);
+
+        let values = deltas
+            .iter()
+            .map(|d| (d.ord, BigInt::from_signed_bytes_be(&d.delta)))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![(1, BigInt::from(100)), (4, BigInt::from(-10))]);
+        assert!(deltas
+            .iter()
+            .all(|d| d.component_id == b"0xc0ffee".to_vec() && d.token == token.to_vec()));
+    }
+
+    fn weth_log(topic0: &str, account: &[u8], value: u64, ordinal: u64) -> Log {
+        let mut account_topic = vec![0u8; 12];
+        account_topic.extend_from_slice(account);
+        let mut data = vec![0u8; 24];
+        data.extend_from_slice(&value.to_be_bytes());
+        Log {
+            address: vec![0xee; 20],
+            topics: vec![hex::decode(topic0).unwrap(), account_topic],
+            data,
+            ordinal,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_transfer_balance_tracker_weth() {
+        let weth = [0xee; 20];
+        let pool = [1; 20];
+        let user = [3; 20];
+        let deposit = "e1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c";
+        let withdrawal = "7fcf532c15f0a6db0bd6d0e038bea71d30d808c7d98cb3bf7268a95bf5081b65";
+        let tx = TransactionTrace {
+            calls: vec![Call {
+                logs: vec![
+                    // Wrap into the pool, then unwrap out of it.
+                    weth_log(deposit, &pool, 100, 1),
+                    weth_log(withdrawal, &pool, 40, 2),
+                    // Wrapping and unwrapping by other accounts does not affect the pool.
+                    weth_log(deposit, &user, 7, 3),
+                    weth_log(withdrawal, &user, 7, 4),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let tracker = TransferBalanceTracker::new("0xc0ffee", &[pool], &[weth]);
+        let deltas = tracker.extract_balance_deltas(&tx);
+
+        let values = deltas
+            .iter()
+            .map(|d| (d.ord, BigInt::from_signed_bytes_be(&d.delta)))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![(1, BigInt::from(100)), (2, BigInt::from(-40))]);
+        assert!(deltas
+            .iter()
+            .all(|d| d.token == weth.to_vec()));
+    }
+
+    #[test]
+    fn test_transfer_balance_tracker_multi_hop() {
+        let (x, y, z) = ([0xaa; 20], [0xbb; 20], [0xcc; 20]);
+        let pool_a = [1; 20];
+        let pool_b = [2; 20];
+        let user = [3; 20];
+        // Route x -> y (pool a), y -> z (pool b), z -> y (pool a again) in a single transaction.
+        let tx = TransactionTrace {
+            calls: vec![Call {
+                logs: vec![
+                    transfer_log(&x, &user, &pool_a, 100, 1),
+                    transfer_log(&y, &pool_a, &pool_b, 50, 2),
+                    transfer_log(&z, &pool_b, &pool_a, 20, 3),
+                    transfer_log(&y, &pool_a, &user, 5, 4),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let tracker_a = TransferBalanceTracker::new("pool_a", &[pool_a], &[x, y, z]);
+        let tracker_b = TransferBalanceTracker::new("pool_b", &[pool_b], &[y, z]);
+
+        let merged = merge_block_balance_deltas(
+            [
+                BlockBalanceDeltas { balance_deltas: tracker_a.extract_balance_deltas(&tx) },
+                BlockBalanceDeltas { balance_deltas: tracker_b.extract_balance_deltas(&tx) },
+            ],
+            DeltaConflict::PreferFirst,
+        );
+        let store = <MockStore as StoreNew>::new();
+        store_balance_changes(merged, store.clone());
+
+        let balance = |component: &str, token: &[u8; 20]| {
+            store.get_last(format!("{component}:{}", hex::encode(token)))
+        };
+        assert_eq!(balance("pool_a", &x), Some(BigInt::from(100)));
+        assert_eq!(balance("pool_a", &y), Some(BigInt::from(-55)));
+        assert_eq!(balance("pool_a", &z), Some(BigInt::from(20)));
+        assert_eq!(balance("pool_b", &x), None);
+        assert_eq!(balance("pool_b", &y), Some(BigInt::from(50)));
+        assert_eq!(balance("pool_b", &z), Some(BigInt::from(-20)));
+    }
+
+    #[test]
+    fn test_extract_balances_from_slots() {
+        let vault = [1u8; 20];
+        let slot = [0u8; 32];
+        let storage_change = |old: &str, new: &str, ordinal: u64| StorageChange {
+            address: vault.to_vec(),
+            key: slot.to_vec(),
+            old_value: hex::decode(old).unwrap(),
+            new_value: hex::decode(new).unwrap(),
+            ordinal,
+        };
+        // | 12 bytes unused | uint112 balance | uint48 other field |
+        let tx = TransactionTrace {
+            calls: vec![
+                Call {
+                    storage_changes: vec![
+                        storage_change(
+                            "0000000000000000000000000000000000000000000000000064000000000001",
+                            "00000000000000000000000000000000000000000000000000c8000000000001",
+                            1,
+                        ),
+                        storage_change(
+                            "00000000000000000000000000000000000000000000000000c8000000000001",
+                            "0000000000000000000000000000000000000000000000000096000000000002",
+                            3,
+                        ),
+                    ],
+                    ..Default::default()
+                },
+                Call {
+                    state_reverted: true,
+                    storage_changes: vec![storage_change(
+                        "0000000000000000000000000000000000000000000000000096000000000002",
+                        "00000000000000000000000000000000000000000000000003e8000000000002",
+                        2,
+                    )],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let balance = SlotBalance::new(&vault, &slot, 6, 14, &[0xaa; 20], "0xc0ffee");
+        let other_field = SlotBalance::new(&vault, &slot, 0, 6, &[0xbb; 20], "0xc0ffee");
+        let untouched = SlotBalance::new(&[2u8; 20], &slot, 6, 14, &[0xaa; 20], "0xc0ffee");
+
+        let res = extract_balances_from_slots(&tx, &[balance, other_field, untouched]);
+
+        assert_eq!(
+            res,
+            vec![
+                BalanceChange {
+                    token: vec![0xaa; 20],
+                    balance: vec![0x96],
+                    component_id: b"0xc0ffee".to_vec(),
+                },
+                BalanceChange {
+                    token: vec![0xbb; 20],
+                    balance: vec![0x02],
+                    component_id: b"0xc0ffee".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_balances_from_slots_deferred_settlement() {
+        let vault = [1u8; 20];
+        let slot = [0u8; 32];
+        let storage_change = |old: u8, new: u8, ordinal: u64| StorageChange {
+            address: vault.to_vec(),
+            key: slot.to_vec(),
+            old_value: vec![old],
+            new_value: vec![new],
+            ordinal,
+        };
+        let balance = SlotBalance::new(&vault, &slot, 0, 32, &[0xaa; 20], "0xc0ffee");
+        // The outer call settles the balance after its nested calls, so its final write has the
+        // highest ordinal even though the call comes first in the trace.
+        let settled = TransactionTrace {
+            calls: vec![
+                Call {
+                    storage_changes: vec![
+                        storage_change(0x10, 0x20, 1),
+                        storage_change(0x30, 0x40, 9),
+                    ],
+                    ..Default::default()
+                },
+                Call { storage_changes: vec![storage_change(0x20, 0x30, 5)], ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        // Nested calls move the balance but the final settlement restores it.
+        let restored = TransactionTrace {
+            calls: vec![
+                Call { storage_changes: vec![storage_change(0x30, 0x10, 9)], ..Default::default() },
+                Call { storage_changes: vec![storage_change(0x10, 0x30, 4)], ..Default::default() },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            extract_balances_from_slots(&settled, &[balance.clone()]),
+            vec![BalanceChange {
+                token: vec![0xaa; 20],
+                balance: vec![0x40],
+                component_id: b"0xc0ffee".to_vec(),
+            }]
+        );
+        assert!(extract_balances_from_slots(&restored, &[balance]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_slot_balance_out_of_bounds() {
+        SlotBalance::new(&[1u8; 20], &[0u8; 32], 20, 14, &[0xaa; 20], "0xc0ffee");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid storage value")]
+    fn test_slot_balance_rejects_long_values() {
+        let spec = SlotBalance::new(&[1u8; 20], &[0u8; 32], 0, 14, &[0xaa; 20], "0xc0ffee");
+        let tx = TransactionTrace {
+            calls: vec![Call {
+                storage_changes: vec![StorageChange {
+                    address: vec![1; 20],
+                    key: vec![0; 32],
+                    old_value: vec![0; 32],
+                    new_value: vec![1; 33],
+                    ordinal: 1,
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        extract_balances_from_slots(&tx, &[spec]);
+    }
+
+    #[test]
+    fn test_merge_block_balance_deltas() {
+        let delta = |tx_index: u64, ord: u64, token: u8, value: i64| BalanceDelta {
+            ord,
+            tx: Some(Transaction {
+                hash: vec![tx_index as u8],
+                from: vec![],
+                to: vec![],
+                index: tx_index,
+            }),
+            token: vec![token],
+            delta: BigInt::from(value).to_signed_bytes_be(),
+            component_id: b"0xc0ffee".to_vec(),
+        };
+        let swaps = BlockBalanceDeltas {
+            balance_deltas: vec![delta(1, 5, 1, 100), delta(1, 5, 2, -50), delta(2, 8, 1, 7)],
+        };
+        let vaults = BlockBalanceDeltas {
+            balance_deltas: vec![delta(1, 3, 1, 10), delta(1, 5, 1, 20), delta(0, 1, 2, 1)],
+        };
+
+        let merged = merge_block_balance_deltas([swaps, vaults], DeltaConflict::Sum);
+
+        assert_eq!(
+            merged.balance_deltas,
+            vec![
+                delta(0, 1, 2, 1),
+                delta(1, 3, 1, 10),
+                delta(1, 5, 1, 120),
+                delta(1, 5, 2, -50),
+                delta(2, 8, 1, 7),
+            ]
+        );
+        // The merged deltas must be accepted by the store.
+        store_balance_changes(merged, <MockStore as StoreNew>::new());
+    }
+
+    #[test]
+    fn test_merge_block_balance_deltas_double_observation() {
+        let delta = |ord: u64, value: i64| BalanceDelta {
+            ord,
+            tx: Some(Transaction { hash: vec![1], from: vec![], to: vec![], index: 1 }),
+            token: vec![1],
+            delta: BigInt::from(value).to_signed_bytes_be(),
+            component_id: b"0xc0ffee".to_vec(),
+        };
+        // Both sources decode the same swap log at ordinal 5; the refresh source also sees a
+        // later event of its own.
+        let swaps = BlockBalanceDeltas { balance_deltas: vec![delta(5, 100)] };
+        let refresh = BlockBalanceDeltas { balance_deltas: vec![delta(5, 100), delta(7, -30)] };
+
+        let merged = merge_block_balance_deltas(
+            [swaps.clone(), refresh.clone()],
+            DeltaConflict::PreferFirst,
+        );
+        assert_eq!(merged.balance_deltas, vec![delta(5, 100), delta(7, -30)]);
+
+        let store = <MockStore as StoreNew>::new();
+        store_balance_changes(merged, store.clone());
+        assert_eq!(store.get_last("0xc0ffee:01"), Some(BigInt::from(70)));
+
+        // Summing is only right for disjoint sources, and must be asked for explicitly.
+        let summed = merge_block_balance_deltas([swaps, refresh], DeltaConflict::Sum);
+        assert_eq!(summed.balance_deltas, vec![delta(5, 200), delta(7, -30)]);
+    }
 }
@@ -14,6 +14,7 @@ use crate::{
     models::{InterimContractChange, TransactionChanges},
     prelude::TransactionChangesBuilder,
 };
+use itertools::Itertools;
 use substreams_ethereum::pb::{
     eth,
     eth::v2::{block::DetailLevel, CallType, TransactionTrace},
@@ -50,18 +51,24 @@ pub fn extract_contract_changes<F: Fn(&[u8]) -> bool>(
     inclusion_predicate: F,
     transaction_changes: &mut HashMap<u64, TransactionChanges>,
 ) {
-    extract_contract_changes_generic(block, inclusion_predicate, |tx, changed_contracts| {
-        transaction_changes
-            .entry(tx.index.into())
-            .or_insert_with(|| TransactionChanges::new(&(tx.into())))
-            .contract_changes
-            .extend(
-                changed_contracts
-                    .clone()
-                    .into_values()
-                    .filter_map(|change| change.into()),
-            );
-    })
+    extract_contract_changes_generic(
+        block,
+        inclusion_predicate,
+        |_, _| true,
+        |tx, changed_contracts| {
+            transaction_changes
+                .entry(tx.index.into())
+                .or_insert_with(|| TransactionChanges::new(&(tx.into())))
+                .contract_changes
+                .extend(
+                    changed_contracts
+                        .clone()
+                        .into_iter()
+                        .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                        .filter_map(|(_, change)| change.into()),
+                );
+        },
+    )
 }
 
 pub fn extract_contract_changes_builder<F: Fn(&[u8]) -> bool>(
@@ -69,23 +76,56 @@ pub fn extract_contract_changes_builder<F: Fn(&[u8]) -> bool>(
     inclusion_predicate: F,
     transaction_changes: &mut HashMap<u64, TransactionChangesBuilder>,
 ) {
-    extract_contract_changes_generic(block, inclusion_predicate, |tx, changed_contracts| {
-        let builder = transaction_changes
-            .entry(tx.index.into())
-            .or_insert_with(|| TransactionChangesBuilder::new(&(tx.into())));
-        changed_contracts
-            .clone()
-            .into_iter()
-            .for_each(|(_, change)| builder.add_contract_changes(&change));
-    })
+    extract_contract_changes_builder_with_slot_filter(
+        block,
+        inclusion_predicate,
+        |_, _| true,
+        transaction_changes,
+    )
+}
+
+/// Same as `extract_contract_changes_builder`, but only keeps the storage slots accepted by
+/// `slot_predicate`.
+///
+/// `slot_predicate` is called with the contract address and the slot key of every storage change
+/// of an included contract. Use it to emit only the slots a simulation actually reads, e.g. with
+/// a per-address allow-list. Balance and code changes of included contracts are unaffected.
+///
+/// Contracts created in the transaction are always emitted as a creation, even if all of their
+/// slots are filtered out. Existing contracts whose only changes were filtered out are omitted.
+pub fn extract_contract_changes_builder_with_slot_filter<
+    F: Fn(&[u8]) -> bool,
+    S: Fn(&[u8], &[u8]) -> bool,
+>(
+    block: &eth::v2::Block,
+    inclusion_predicate: F,
+    slot_predicate: S,
+    transaction_changes: &mut HashMap<u64, TransactionChangesBuilder>,
+) {
+    extract_contract_changes_generic(
+        block,
+        inclusion_predicate,
+        slot_predicate,
+        |tx, changed_contracts| {
+            let builder = transaction_changes
+                .entry(tx.index.into())
+                .or_insert_with(|| TransactionChangesBuilder::new(&(tx.into())));
+            changed_contracts
+                .clone()
+                .into_iter()
+                .for_each(|(_, change)| builder.add_contract_changes(&change));
+        },
+    )
 }
 
 fn extract_contract_changes_generic<
     F: Fn(&[u8]) -> bool,
+    S: Fn(&[u8], &[u8]) -> bool,
     G: FnMut(&TransactionTrace, &HashMap<Vec<u8>, InterimContractChange>),
 >(
     block: &eth::v2::Block,
     inclusion_predicate: F,
+    slot_predicate: S,
     mut store_changes: G,
 ) {
     if block.detail_level != Into::<i32>::into(DetailLevel::DetaillevelExtended) {
@@ -141,7 +181,11 @@ fn extract_contract_changes_generic<
                             )
                         });
 
-                    contract_change.upsert_slot(storage_change);
+                    // The contract is registered even if the slot is filtered out, so that
+                    // creations are not lost. Empty updates are dropped on conversion.
+                    if slot_predicate(&storage_change.address, &storage_change.key) {
+                        contract_change.upsert_slot(storage_change);
+                    }
                 });
 
             balance_changes
@@ -187,3 +231,94 @@ fn extract_contract_changes_generic<
             changed_contracts.clear()
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChangeType, ContractSlot};
+    use substreams_ethereum::pb::eth::v2::{
+        BalanceChange, BigInt, Call, CodeChange, StorageChange, TransactionTraceStatus,
+    };
+
+    fn storage_change(address: &[u8], slot: u8, ordinal: u64) -> StorageChange {
+        StorageChange {
+            address: address.to_vec(),
+            key: vec![slot; 32],
+            old_value: vec![0; 32],
+            new_value: vec![1; 32],
+            ordinal,
+        }
+    }
+
+    #[test]
+    fn test_extract_contract_changes_with_slot_filter() {
+        let existing = [1u8; 20];
+        let created = [3u8; 20];
+        let block = eth::v2::Block {
+            detail_level: DetailLevel::DetaillevelExtended.into(),
+            transaction_traces: vec![TransactionTrace {
+                status: TransactionTraceStatus::Succeeded.into(),
+                calls: vec![
+                    Call {
+                        call_type: CallType::Call.into(),
+                        address: existing.to_vec(),
+                        storage_changes: vec![
+                            storage_change(&existing, 1, 1),
+                            storage_change(&existing, 2, 2),
+                        ],
+                        balance_changes: vec![BalanceChange {
+                            address: existing.to_vec(),
+                            new_value: Some(BigInt { bytes: vec![5] }),
+                            ordinal: 3,
+                            ..Default::default()
+                        }],
+                        code_changes: vec![CodeChange {
+                            address: existing.to_vec(),
+                            new_code: vec![0x60],
+                            ordinal: 4,
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    Call {
+                        call_type: CallType::Create.into(),
+                        address: created.to_vec(),
+                        storage_changes: vec![storage_change(&created, 2, 5)],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut transaction_changes = HashMap::new();
+
+        extract_contract_changes_builder_with_slot_filter(
+            &block,
+            |address| address == existing || address == created,
+            |_, slot| slot == [1u8; 32],
+            &mut transaction_changes,
+        );
+
+        let changes = transaction_changes
+            .remove(&0)
+            .and_then(|builder| builder.build())
+            .expect("contract changes should be extracted");
+        assert_eq!(changes.contract_changes.len(), 2);
+
+        let existing_change = &changes.contract_changes[0];
+        assert_eq!(existing_change.address, existing.to_vec());
+        assert_eq!(
+            existing_change.slots,
+            vec![ContractSlot { slot: vec![1; 32], value: vec![1; 32] }]
+        );
+        assert_eq!(existing_change.balance, vec![5]);
+        assert_eq!(existing_change.code, vec![0x60]);
+        assert_eq!(existing_change.change, i32::from(ChangeType::Update));
+
+        let created_change = &changes.contract_changes[1];
+        assert_eq!(created_change.address, created.to_vec());
+        assert!(created_change.slots.is_empty());
+        assert_eq!(created_change.change, i32::from(ChangeType::Creation));
+    }
+}
@@ -0,0 +1,68 @@
+//! Helpers to emit entrypoints for Tycho's dynamic contract indexing (DCI).
+//!
+//! The DCI traces entrypoints to discover which contracts and storage slots a component
+//! depends on (e.g. oracles or rate models called by a pool). An entrypoint is identified by a
+//! target contract and a function signature; `EntryPointParams` tell the indexer how to call it.
+use crate::models::{entry_point_params::TraceData, EntryPoint, EntryPointParams, RpcTraceData};
+
+/// Creates an entrypoint together with the parameters to trace it.
+///
+/// The entrypoint id follows the recommended `target:signature` format, with the target as a 0x
+/// prefixed hex string.
+///
+/// ## Arguments
+/// * `target` - Address of the contract to trace.
+/// * `signature` - Signature of the function to trace, e.g. `getQuote(uint256,address,address)`.
+/// * `component_id` - Id of the component that depends on this entrypoint.
+/// * `trace_data` - The strategy and data used to trace the entrypoint.
+pub fn create_entrypoint(
+    target: &[u8],
+    signature: &str,
+    component_id: &str,
+    trace_data: TraceData,
+) -> (EntryPoint, EntryPointParams) {
+    let entrypoint_id = format!("0x{}:{}", hex::encode(target), signature);
+    let entrypoint = EntryPoint {
+        id: entrypoint_id.clone(),
+        target: target.to_vec(),
+        signature: signature.to_string(),
+        component_id: component_id.to_string(),
+    };
+    let params = EntryPointParams {
+        entrypoint_id,
+        component_id: component_id.to_string(),
+        trace_data: Some(trace_data),
+    };
+    (entrypoint, params)
+}
+
+/// Builds RPC trace data for the given calldata.
+///
+/// An empty `caller` lets the indexer use the chain's default caller.
+pub fn rpc_trace_data(caller: &[u8], calldata: &[u8]) -> TraceData {
+    TraceData::Rpc(RpcTraceData { caller: caller.to_vec(), calldata: calldata.to_vec() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_entrypoint() {
+        let (entrypoint, params) = create_entrypoint(
+            &[0xab; 20],
+            "getQuote(uint256,address,address)",
+            "0x01",
+            rpc_trace_data(&[], &[1, 2, 3]),
+        );
+
+        let expected_id = format!("0x{}:getQuote(uint256,address,address)", "ab".repeat(20));
+        assert_eq!(entrypoint.id, expected_id);
+        assert_eq!(entrypoint.target, vec![0xab; 20]);
+        assert_eq!(params.entrypoint_id, expected_id);
+        assert_eq!(
+            params.trace_data,
+            Some(TraceData::Rpc(RpcTraceData { caller: vec![], calldata: vec![1, 2, 3] }))
+        );
+    }
+}
@@ -3,7 +3,8 @@ pub mod attributes;
 pub mod balances;
 pub mod block_storage;
 pub mod contract;
-mod mock_store;
+pub mod entrypoint;
+pub mod mock_store;
 pub mod models;
 pub mod pb;
 
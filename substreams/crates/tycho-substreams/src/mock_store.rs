@@ -1,95 +1,296 @@
-//! Contains a mock store for internal testing.
+//! In-memory store doubles for testing handlers.
 //!
-//! Might make this public alter to users can test their store handlers.
+//! `MockStore` implements the substreams store traits so handlers that are generic over
+//! `StoreGet`, `StoreSet` or `StoreAdd` (e.g. `store_balance_changes`) can be unit-tested
+//! without the substreams runtime. Every write is recorded, so the store can also produce the
+//! `StoreDeltas` a downstream module in deltas mode would receive.
+//!
+//! ## Example
+//! ```ignore
+//! let store = <MockStore as StoreNew>::new();
+//! store_balance_changes(deltas.clone(), store.clone());
+//! let balances = aggregate_balances_changes(store.deltas(), deltas);
+//! ```
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use substreams::{
+    pb::substreams::{store_delta::Operation, StoreDelta, StoreDeltas},
     prelude::{BigInt, StoreDelete, StoreGet, StoreNew},
-    store::StoreAdd,
+    store::{StoreAdd, StoreSet, StoreSetIfNotExists},
 };
 
-type BigIntStore = HashMap<String, Vec<(u64, BigInt)>>;
+#[derive(Debug)]
+struct MockStoreData<V> {
+    /// Every value a key took, in write order, together with the ordinal of the write.
+    values: HashMap<String, Vec<(u64, V)>>,
+    /// Write log, used to build `StoreDeltas`.
+    deltas: Vec<StoreDelta>,
+}
 
-#[derive(Debug, Clone)]
-pub struct MockStore {
-    data: Rc<RefCell<BigIntStore>>,
+impl<V> Default for MockStoreData<V> {
+    fn default() -> Self {
+        Self { values: HashMap::new(), deltas: Vec::new() }
+    }
 }
 
-impl StoreDelete for MockStore {
-    fn delete_prefix(&self, _ord: i64, prefix: &String) {
-        self.data
-            .borrow_mut()
-            .retain(|k, _| !k.starts_with(prefix));
+/// In-memory store keeping the full value history of each key.
+///
+/// Clones share the same underlying data, so a clone can be handed to a handler while the
+/// original is used for assertions afterwards. Defaults to `BigInt` values, which is what
+/// additive balance stores hold; use `MockStore<String>` as a stand-in for
+/// `StoreSetString`/`StoreGetString` and `MockStore<Vec<u8>>` for the raw variants.
+#[derive(Debug)]
+pub struct MockStore<V = BigInt> {
+    data: Rc<RefCell<MockStoreData<V>>>,
+}
+
+impl<V> Clone for MockStore<V> {
+    fn clone(&self) -> Self {
+        Self { data: Rc::clone(&self.data) }
+    }
+}
+
+impl<V> Default for MockStore<V> {
+    fn default() -> Self {
+        Self { data: Rc::new(RefCell::new(MockStoreData::default())) }
+    }
+}
+
+/// Encoding used when recording deltas for a value type.
+///
+/// Mirrors substreams: `BigInt` and `String` values are utf-8 strings, raw values are kept as is.
+pub trait MockStoreValue: Clone {
+    fn encode(&self) -> Vec<u8>;
+}
+
+impl MockStoreValue for BigInt {
+    fn encode(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl MockStoreValue for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl MockStoreValue for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl<V: MockStoreValue> MockStore<V> {
+    /// Writes a value and records the corresponding delta.
+    fn write(&self, ord: u64, key: &str, value: V) {
+        let mut guard = self.data.borrow_mut();
+        let data = &mut *guard;
+        let history = data
+            .values
+            .entry(key.to_string())
+            .or_default();
+        let (operation, old_value) = match history.last() {
+            Some((_, previous)) => (Operation::Update, previous.encode()),
+            None => (Operation::Create, Vec::new()),
+        };
+        data.deltas.push(StoreDelta {
+            operation: operation.into(),
+            ordinal: ord,
+            key: key.to_string(),
+            old_value,
+            new_value: value.encode(),
+        });
+        history.push((ord, value));
+    }
+
+    /// Returns all writes so far as `StoreDeltas`, in write order.
+    pub fn deltas(&self) -> StoreDeltas {
+        StoreDeltas { deltas: self.data.borrow().deltas.clone() }
+    }
+
+    /// Keys currently present in the store, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self
+            .data
+            .borrow()
+            .values
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
     }
 }
 
-impl StoreNew for MockStore {
+impl<V: MockStoreValue> StoreDelete for MockStore<V> {
+    fn delete_prefix(&self, ord: i64, prefix: &String) {
+        let mut guard = self.data.borrow_mut();
+        let data = &mut *guard;
+        let mut deleted = data
+            .values
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        deleted.sort();
+        for key in deleted {
+            // Like the substreams runtime, deletions carry the value the key held.
+            let old_value = data
+                .values
+                .remove(&key)
+                .and_then(|history| {
+                    history
+                        .last()
+                        .map(|(_, value)| value.encode())
+                })
+                .unwrap_or_default();
+            data.deltas.push(StoreDelta {
+                operation: Operation::Delete.into(),
+                ordinal: ord as u64,
+                key,
+                old_value,
+                new_value: Vec::new(),
+            });
+        }
+    }
+}
+
+impl<V> StoreNew for MockStore<V> {
     fn new() -> Self {
-        Self { data: Rc::new(RefCell::new(HashMap::new())) }
+        Self { data: Rc::new(RefCell::new(MockStoreData::default())) }
     }
 }
 
-impl StoreAdd<BigInt> for MockStore {
+impl StoreAdd<BigInt> for MockStore<BigInt> {
     fn add<K: AsRef<str>>(&self, ord: u64, key: K, value: BigInt) {
-        let mut guard = self.data.borrow_mut();
-        guard
-            .entry(key.as_ref().to_string())
-            .and_modify(|v| {
-                let prev_value = v.last().unwrap().1.clone();
-                v.push((ord, prev_value + value.clone()));
-            })
-            .or_insert(vec![(ord, value)]);
+        let new_value = match self.get_last(key.as_ref()) {
+            Some(previous) => previous + value,
+            None => value,
+        };
+        self.write(ord, key.as_ref(), new_value);
+    }
+
+    fn add_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: BigInt) {
+        for key in keys {
+            self.add(ord, key, value.clone());
+        }
+    }
+}
+
+impl<V: MockStoreValue> StoreSet<V> for MockStore<V> {
+    fn set<K: AsRef<str>>(&self, ord: u64, key: K, value: &V) {
+        self.write(ord, key.as_ref(), value.clone());
+    }
+
+    fn set_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &V) {
+        for key in keys {
+            self.set(ord, key, value);
+        }
+    }
+}
+
+impl<V: MockStoreValue> StoreSetIfNotExists<V> for MockStore<V> {
+    fn set_if_not_exists<K: AsRef<str>>(&self, ord: u64, key: K, value: &V) {
+        if self.get_last(key.as_ref()).is_none() {
+            self.set(ord, key, value);
+        }
     }
 
-    fn add_many<K: AsRef<str>>(&self, _ord: u64, _keys: &Vec<K>, _value: BigInt) {
-        todo!()
+    fn set_if_not_exists_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &V) {
+        for key in keys {
+            self.set_if_not_exists(ord, key, value);
+        }
     }
 }
 
-impl StoreGet<BigInt> for MockStore {
+impl<V: Clone> StoreGet<V> for MockStore<V> {
     fn new(_idx: u32) -> Self {
-        Self { data: Rc::new(RefCell::new(HashMap::new())) }
+        Self { data: Rc::new(RefCell::new(MockStoreData::default())) }
     }
 
-    fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<BigInt> {
+    /// Returns the value the key had at the given ordinal, i.e. the last value written at or
+    /// before `ord`.
+    fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<V> {
         self.data
             .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| {
+            .values
+            .get(key.as_ref())
+            .and_then(|v| {
                 v.iter()
-                    .find(|(current_ord, _)| *current_ord == ord)
-                    .unwrap()
-                    .1
-                    .clone()
+                    .rev()
+                    .find(|(current_ord, _)| *current_ord <= ord)
+                    .map(|(_, value)| value.clone())
             })
     }
 
-    fn get_last<K: AsRef<str>>(&self, key: K) -> Option<BigInt> {
+    fn get_last<K: AsRef<str>>(&self, key: K) -> Option<V> {
         self.data
             .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.last().unwrap().1.clone())
+            .values
+            .get(key.as_ref())
+            .and_then(|v| v.last())
+            .map(|(_, value)| value.clone())
     }
 
-    fn get_first<K: AsRef<str>>(&self, key: K) -> Option<BigInt> {
+    fn get_first<K: AsRef<str>>(&self, key: K) -> Option<V> {
         self.data
             .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.first().unwrap().1.clone())
+            .values
+            .get(key.as_ref())
+            .and_then(|v| v.first())
+            .map(|(_, value)| value.clone())
     }
 
     fn has_at<K: AsRef<str>>(&self, ord: u64, key: K) -> bool {
-        self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.iter().any(|(v, _)| *v == ord))
-            .unwrap_or(false)
+        self.get_at(ord, key).is_some()
+    }
+
+    fn has_last<K: AsRef<str>>(&self, key: K) -> bool {
+        self.get_last(key).is_some()
+    }
+
+    fn has_first<K: AsRef<str>>(&self, key: K) -> bool {
+        self.get_first(key).is_some()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_store_set_get() {
+        let store = <MockStore<String> as StoreNew>::new();
 
-    fn has_last<K: AsRef<str>>(&self, _key: K) -> bool {
-        todo!()
+        store.set(1, "pool:0x01", &"a".to_string());
+        store.set(5, "pool:0x01", &"b".to_string());
+        store.set_if_not_exists(6, "pool:0x01", &"c".to_string());
+
+        assert_eq!(store.get_first("pool:0x01"), Some("a".to_string()));
+        assert_eq!(store.get_last("pool:0x01"), Some("b".to_string()));
+        assert_eq!(store.get_at(3, "pool:0x01"), Some("a".to_string()));
+        assert_eq!(store.get_at(0, "pool:0x01"), None);
+        assert!(!store.has_last("pool:0x02"));
     }
 
-    fn has_first<K: AsRef<str>>(&self, _key: K) -> bool {
-        todo!()
+    #[test]
+    fn test_deltas() {
+        let store = <MockStore as StoreNew>::new();
+
+        store.add(1, "c:t", BigInt::from(10));
+        store.add(2, "c:t", BigInt::from(-3));
+        store.delete_prefix(3, &"c:".to_string());
+
+        let deltas = store.deltas().deltas;
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[0].operation, i32::from(Operation::Create));
+        assert_eq!(deltas[0].new_value, b"10".to_vec());
+        assert_eq!(deltas[1].operation, i32::from(Operation::Update));
+        assert_eq!(deltas[1].old_value, b"10".to_vec());
+        assert_eq!(deltas[1].new_value, b"7".to_vec());
+        assert_eq!(deltas[2].operation, i32::from(Operation::Delete));
+        assert_eq!(deltas[2].old_value, b"7".to_vec());
+        assert!(deltas[2].new_value.is_empty());
+        assert!(store.keys().is_empty());
     }
 }
@@ -1,6 +1,9 @@
+use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
 use substreams_ethereum::pb::eth::v2::{self as sf, StorageChange};
 
+use crate::entrypoint::create_entrypoint;
+
 // re-export the protobuf types here.
 pub use crate::pb::tycho::evm::v1::*;
 
@@ -29,6 +32,7 @@ pub struct TransactionChangesBuilder {
     component_changes: HashMap<String, ProtocolComponent>,
     balance_changes: HashMap<(Vec<u8>, Vec<u8>), BalanceChange>,
     entrypoints: HashSet<EntryPoint>,
+    entrypoint_params: Vec<EntryPointParams>,
 }
 
 impl TransactionChangesBuilder {
@@ -159,32 +163,75 @@ impl TransactionChangesBuilder {
             .insert(entrypoint.clone());
     }
 
+    /// Adds tracing parameters for an entrypoint.
+    ///
+    /// An entrypoint may be traced with several parameter sets (e.g. different calldata), so
+    /// these are accumulated. Identical parameters are only added once.
+    pub fn add_entrypoint_params(&mut self, params: &EntryPointParams) {
+        if !self.entrypoint_params.contains(params) {
+            self.entrypoint_params
+                .push(params.clone());
+        }
+    }
+
+    /// Adds an entrypoint and its tracing parameters to the transaction.
+    ///
+    /// Convenience wrapper around `create_entrypoint`, `add_entrypoint` and
+    /// `add_entrypoint_params`.
+    pub fn add_traced_entrypoint(
+        &mut self,
+        target: &[u8],
+        signature: &str,
+        component_id: &str,
+        trace_data: entry_point_params::TraceData,
+    ) {
+        let (entrypoint, params) = create_entrypoint(target, signature, component_id, trace_data);
+        self.add_entrypoint(&entrypoint);
+        self.add_entrypoint_params(&params);
+    }
+
+    /// Builds the `TransactionChanges`.
+    ///
+    /// The output is deterministic: contract changes are sorted by address, entity and component
+    /// changes by component id, balance changes by component id and token and entrypoints by id.
+    /// Entrypoint params keep their insertion order.
+    ///
+    /// Returns `None` if no changes were registered.
     pub fn build(self) -> Option<TransactionChanges> {
         let tx_changes = TransactionChanges {
             tx: self.tx,
             contract_changes: self
                 .contract_changes
-                .into_values()
-                .filter_map(|interim| interim.into())
+                .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                .filter_map(|(_, interim)| interim.into())
                 .collect::<Vec<_>>(),
             entity_changes: self
                 .entity_changes
-                .into_values()
-                .filter_map(|interim| interim.into())
+                .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                .filter_map(|(_, interim)| interim.into())
                 .collect::<Vec<_>>(),
             component_changes: self
                 .component_changes
-                .into_values()
+                .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, component)| component)
                 .collect::<Vec<_>>(),
             balance_changes: self
                 .balance_changes
-                .into_values()
+                .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, change)| change)
                 .collect::<Vec<_>>(),
             entrypoints: self
                 .entrypoints
                 .into_iter()
+                .sorted_unstable_by(|a, b| {
+                    (&a.id, &a.component_id, &a.target).cmp(&(&b.id, &b.component_id, &b.target))
+                })
                 .collect::<Vec<_>>(),
-            entrypoint_params: Vec::new(), // TODO: Add entrypoint params to builder
+            entrypoint_params: self.entrypoint_params,
         };
         if tx_changes.is_empty() {
             None
@@ -305,7 +352,7 @@ impl ProtocolComponent {
     /// Designates this component as a swap type within the protocol.
     ///
     /// Sets the `protocol_type` accordingly, including `financial_type` as `Swap` and leaving
-    /// `attribute_schema` empty.
+    /// `attribute_schema` empty, see `with_attribute_schema` to populate it.
     ///
     /// ## Parameters
     /// - `name`: The name of the swap protocol.
@@ -320,6 +367,36 @@ impl ProtocolComponent {
         self
     }
 
+    /// Sets the attribute schema of this component's protocol type.
+    ///
+    /// The schema lists the attributes a component of this type carries, so consumers can
+    /// validate and type them. Each entry is a key-value pair of attribute name and a value
+    /// describing it (e.g. its encoding).
+    ///
+    /// ## Parameters
+    /// - `schema`: Slice of key-value pairs describing the attributes.
+    ///
+    /// ## Panics
+    /// If the protocol type has not been set yet, e.g. via `as_swap_type`.
+    pub fn with_attribute_schema<K: AsRef<str>, V: AsRef<[u8]>>(
+        mut self,
+        schema: &[(K, V)],
+    ) -> Self {
+        let protocol_type = self
+            .protocol_type
+            .as_mut()
+            .expect("Protocol type must be set before the attribute schema");
+        protocol_type.attribute_schema = schema
+            .iter()
+            .map(|(k, v)| Attribute {
+                name: k.as_ref().to_string(),
+                value: v.as_ref().to_vec(),
+                change: ChangeType::Creation.into(),
+            })
+            .collect::<Vec<Attribute>>();
+        self
+    }
+
     /// Checks if the instance contains all specified attributes.
     ///
     /// This function verifies whether the `ProtocolComponent` has all the given static attributes.
@@ -439,13 +516,50 @@ impl InterimEntityChanges {
     }
 }
 
+/// Merges the entity changes of a single transaction.
+///
+/// Each `EntityChanges` is tagged with the ordinal at which it happened. Changes for the same
+/// component are merged into a single `EntityChanges` and, per attribute name, the change with
+/// the highest ordinal wins; changes with equal ordinals are applied in input order. Creation and
+/// deletion are handled like in `TransactionChangesBuilder::add_entity_change`.
+///
+/// Use this if several code paths emit attributes for the same component within a transaction,
+/// so the output never carries duplicate or conflicting attribute updates.
+///
+/// ## Returns
+/// The merged entity changes, sorted by component id, with attributes sorted by name. Components
+/// left without attributes are omitted.
+pub fn merge_entity_changes(
+    changes: impl IntoIterator<Item = (u64, EntityChanges)>,
+) -> Vec<EntityChanges> {
+    let mut merged: HashMap<String, InterimEntityChanges> = HashMap::new();
+    changes
+        .into_iter()
+        .sorted_by_key(|(ordinal, _)| *ordinal)
+        .for_each(|(_, change)| {
+            let entry = merged
+                .entry(change.component_id.clone())
+                .or_insert_with(|| InterimEntityChanges::new(&change.component_id));
+            for attr in change.attributes.iter() {
+                entry.set_attribute(attr);
+            }
+        });
+    merged
+        .into_iter()
+        .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+        .filter_map(|(_, interim)| interim.into())
+        .collect()
+}
+
 impl From<InterimEntityChanges> for Option<EntityChanges> {
     fn from(value: InterimEntityChanges) -> Self {
         let changes = EntityChanges {
             component_id: value.component_id.clone(),
             attributes: value
                 .attributes
-                .into_values()
+                .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, attr)| attr)
                 .collect::<Vec<_>>(),
         };
         if changes.attributes.is_empty() {
@@ -559,6 +673,7 @@ impl From<InterimContractChange> for Option<ContractChange> {
             slots: value
                 .slots
                 .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
                 .filter(|(_, value)| value.has_changed())
                 .map(|(slot, value)| ContractSlot { slot, value: value.new_value })
                 .collect(),
@@ -566,6 +681,7 @@ impl From<InterimContractChange> for Option<ContractChange> {
             token_balances: value
                 .token_balances
                 .into_iter()
+                .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
                 .map(|(k, v)| AccountBalanceChange { token: k, balance: v })
                 .collect(),
         };
@@ -591,7 +707,9 @@ impl TransactionChanges {
         self.contract_changes.is_empty() &&
             self.component_changes.is_empty() &&
             self.balance_changes.is_empty() &&
-            self.entity_changes.is_empty()
+            self.entity_changes.is_empty() &&
+            self.entrypoints.is_empty() &&
+            self.entrypoint_params.is_empty()
     }
 }
 
@@ -601,7 +719,11 @@ mod test {
 
     use crate::models::{Attribute, ChangeType, EntityChanges};
 
-    use super::{InterimContractChange, TransactionChangesBuilder};
+    use super::{
+        merge_entity_changes, ImplementationType, InterimContractChange, ProtocolComponent,
+        TransactionChangesBuilder,
+    };
+    use crate::entrypoint::rpc_trace_data;
 
     #[test]
     fn test_transaction_changes_builder_ignored_contract_changes() {
@@ -651,4 +773,145 @@ mod test {
         let tx_changes = builder.build();
         assert!(tx_changes.is_none());
     }
+
+    #[test]
+    fn test_with_attribute_schema() {
+        let component = ProtocolComponent::new("component")
+            .as_swap_type("protocol", ImplementationType::Vm)
+            .with_attribute_schema(&[("fee", "uint256"), ("reserves", "json")]);
+
+        let schema = component
+            .protocol_type
+            .unwrap()
+            .attribute_schema;
+        assert_eq!(schema.len(), 2);
+        assert_eq!(schema[0].name, "fee");
+        assert_eq!(schema[0].value, b"uint256".to_vec());
+        assert_eq!(schema[0].change, i32::from(ChangeType::Creation));
+    }
+
+    #[test]
+    fn test_transaction_changes_builder_deterministic_order() {
+        let mut builder = TransactionChangesBuilder::new(&super::Transaction::default());
+        for id in ["c", "a", "b"] {
+            builder.add_protocol_component(&ProtocolComponent::new(id));
+            builder.add_entity_change(&EntityChanges {
+                component_id: id.to_string(),
+                attributes: ["z", "x", "y"]
+                    .iter()
+                    .map(|name| Attribute {
+                        name: name.to_string(),
+                        value: vec![1],
+                        change: ChangeType::Update.into(),
+                    })
+                    .collect(),
+            });
+            builder.add_balance_change(&super::BalanceChange {
+                token: vec![2],
+                balance: vec![1],
+                component_id: id.as_bytes().to_vec(),
+            });
+        }
+        for address in [[3u8], [1u8], [2u8]] {
+            let mut change = InterimContractChange::new(&address, false);
+            for slot in [[9u8], [7u8], [8u8]] {
+                change.upsert_slot(&StorageChange {
+                    address: address.to_vec(),
+                    key: slot.to_vec(),
+                    old_value: vec![0],
+                    new_value: vec![1],
+                    ordinal: 0,
+                });
+            }
+            builder.add_contract_changes(&change);
+        }
+
+        let changes = builder.build().unwrap();
+
+        let component_ids: Vec<_> = changes
+            .component_changes
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect();
+        assert_eq!(component_ids, ["a", "b", "c"]);
+        let entity_ids: Vec<_> = changes
+            .entity_changes
+            .iter()
+            .map(|c| c.component_id.as_str())
+            .collect();
+        assert_eq!(entity_ids, ["a", "b", "c"]);
+        let attribute_names: Vec<_> = changes.entity_changes[0]
+            .attributes
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(attribute_names, ["x", "y", "z"]);
+        let balance_ids: Vec<_> = changes
+            .balance_changes
+            .iter()
+            .map(|b| b.component_id.clone())
+            .collect();
+        assert_eq!(balance_ids, [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        let addresses: Vec<_> = changes
+            .contract_changes
+            .iter()
+            .map(|c| c.address.clone())
+            .collect();
+        assert_eq!(addresses, [vec![1], vec![2], vec![3]]);
+        let slots: Vec<_> = changes.contract_changes[0]
+            .slots
+            .iter()
+            .map(|s| s.slot.clone())
+            .collect();
+        assert_eq!(slots, [vec![7], vec![8], vec![9]]);
+    }
+
+    #[test]
+    fn test_merge_entity_changes() {
+        let attr = |name: &str, value: u8| Attribute {
+            name: name.to_string(),
+            value: vec![value],
+            change: ChangeType::Update.into(),
+        };
+        let changes = vec![
+            (5, EntityChanges { component_id: "b".to_string(), attributes: vec![attr("x", 2)] }),
+            (
+                1,
+                EntityChanges {
+                    component_id: "b".to_string(),
+                    attributes: vec![attr("x", 1), attr("y", 1)],
+                },
+            ),
+            (3, EntityChanges { component_id: "a".to_string(), attributes: vec![attr("z", 1)] }),
+            (5, EntityChanges { component_id: "b".to_string(), attributes: vec![attr("y", 3)] }),
+        ];
+
+        let merged = merge_entity_changes(changes);
+
+        assert_eq!(
+            merged,
+            vec![
+                EntityChanges { component_id: "a".to_string(), attributes: vec![attr("z", 1)] },
+                EntityChanges {
+                    component_id: "b".to_string(),
+                    attributes: vec![attr("x", 2), attr("y", 3)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builder_keeps_entrypoint_only_changes() {
+        let mut builder = TransactionChangesBuilder::new(&super::Transaction::default());
+        let trace_data = rpc_trace_data(&[], &[1]);
+
+        builder.add_traced_entrypoint(&[1; 20], "rate()", "0x01", trace_data.clone());
+        builder.add_traced_entrypoint(&[1; 20], "rate()", "0x01", trace_data);
+
+        let changes = builder
+            .build()
+            .expect("entrypoints should not be dropped");
+        assert_eq!(changes.entrypoints.len(), 1);
+        assert_eq!(changes.entrypoint_params.len(), 1);
+    }
 }